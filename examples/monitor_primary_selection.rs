@@ -1,27 +1,24 @@
 extern crate x11_clipboard;
 
-use x11_clipboard::Clipboard;
+use std::time::Duration;
+use x11_clipboard::{ Clipboard, Selection, SelectionWatcher, ChangeKind };
 
 
 fn main() {
     let clipboard = Clipboard::new().unwrap();
-    let mut last = String::new();
+    let watcher = SelectionWatcher::new(vec![Selection::Primary]).unwrap();
 
     println!("Waiting for selection...");
 
-    loop {
-        if let Ok(curr) = clipboard.load_wait(
-            clipboard.getter.atoms.primary,
-            clipboard.getter.atoms.utf8_string,
-            clipboard.getter.atoms.property
-        ) {
-            let curr = String::from_utf8_lossy(&curr);
-            let curr = curr
-                .trim_matches('\u{0}')
-                .trim();
-            if !curr.is_empty() && last != curr {
-                last = curr.to_owned();
-                println!("Contents of primary selection: {}", last);
+    for event in watcher {
+        if event.kind != ChangeKind::SetOwner {
+            continue
+        }
+
+        if let Ok(curr) = clipboard.load_text(event.selection, Duration::from_secs(3)) {
+            let curr = curr.trim();
+            if !curr.is_empty() {
+                println!("Contents of primary selection: {}", curr);
                 println!("Waiting for selection...");
             }
         }
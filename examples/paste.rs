@@ -6,15 +6,7 @@ use x11_clipboard::Clipboard;
 
 fn main() {
     let clipboard = Clipboard::new().unwrap();
-    let val =
-        clipboard.load(
-            clipboard.setter.atoms.clipboard,
-            clipboard.setter.atoms.utf8_string,
-            clipboard.setter.atoms.property,
-            Duration::from_secs(3)
-        )
-        .unwrap();
-    let val = String::from_utf8(val).unwrap();
+    let val = clipboard.get_text(Duration::from_secs(3)).unwrap();
 
     print!("{}", val);
 }
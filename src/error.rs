@@ -1,11 +1,16 @@
 use xcb::Atom;
 use xcb::base::{ ConnError, GenericError };
-use std::fmt;
+use std::{ fmt, io };
+use std::path::PathBuf;
+use std::string::FromUtf8Error;
 use std::sync::mpsc::SendError;
 use std::error::Error as StdError;
+#[cfg(feature = "image")]
+use image::ImageError;
 
 #[must_use]
 #[derive(Debug)]
+#[allow(clippy::manual_non_exhaustive)]
 pub enum Error {
     Set(SendError<Atom>),
     XcbConn(ConnError),
@@ -15,6 +20,16 @@ pub enum Error {
     Owner,
     UnexpectedType(Atom),
     IoError,
+    NoManager,
+    Persist,
+    MissingExtension(&'static str),
+    Write(io::Error),
+    TooLarge(usize),
+    Utf8(FromUtf8Error),
+    NoTarget,
+    RelativePath(PathBuf),
+    #[cfg(feature = "image")]
+    Image(ImageError),
 
     #[doc(hidden)]
     __Unknown
@@ -32,6 +47,16 @@ impl fmt::Display for Error {
             Owner => write!(f, "Failed to set new owner of XCB selection"),
             UnexpectedType(target) => write!(f, "Unexpected Reply type: {}", target),
             IoError => write!(f, "XCB: IO error"),
+            NoManager => write!(f, "No clipboard manager is running"),
+            Persist => write!(f, "Clipboard manager failed to save the selection"),
+            MissingExtension(name) => write!(f, "X server lacks the {} extension", name),
+            Write(e) => write!(f, "Failed to write the loaded value: {}", e),
+            TooLarge(limit) => write!(f, "Selection exceeds the maximum size of {} bytes", limit),
+            Utf8(e) => write!(f, "Selection is not valid UTF-8: {}", e),
+            NoTarget => write!(f, "Selection owner offers none of the requested targets"),
+            RelativePath(path) => write!(f, "File path is not absolute: {}", path.display()),
+            #[cfg(feature = "image")]
+            Image(e) => write!(f, "Failed to decode the image: {}", e),
             __Unknown => unreachable!()
         }
     }
@@ -44,7 +69,12 @@ impl StdError for Error {
             Set(e) => Some(e),
             XcbConn(e) => Some(e),
             XcbGeneric(e) => Some(e),
-            Lock | Timeout | Owner | UnexpectedType(_) | IoError => None,
+            Write(e) => Some(e),
+            Utf8(e) => Some(e),
+            #[cfg(feature = "image")]
+            Image(e) => Some(e),
+            Lock | Timeout | Owner | UnexpectedType(_) | IoError | NoManager | Persist
+                | MissingExtension(_) | TooLarge(_) | NoTarget | RelativePath(_) => None,
             __Unknown => unreachable!()
         }
     }
@@ -63,3 +93,6 @@ macro_rules! define_from {
 define_from!(Set from SendError<Atom>);
 define_from!(XcbConn from ConnError);
 define_from!(XcbGeneric from GenericError);
+define_from!(Utf8 from FromUtf8Error);
+#[cfg(feature = "image")]
+define_from!(Image from ImageError);
@@ -0,0 +1,156 @@
+use std::ffi::OsString;
+use std::os::unix::ffi::{ OsStrExt, OsStringExt };
+use std::path::{ Path, PathBuf };
+use xcb::Atom;
+use error::Error;
+use provider::Provider;
+use { Context, Target };
+
+
+/// Whether pasted files are copied or moved, as file managers advertise it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileOperation {
+    Copy,
+    Cut
+}
+
+/// A list of files, as exchanged by file managers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileList {
+    pub operation: FileOperation,
+    /// Absolute paths of the files.
+    pub paths: Vec<PathBuf>
+}
+
+pub fn uri_list() -> Target {
+    Target::text_uri_list()
+}
+
+pub fn gnome_copied_files() -> Target {
+    Target::mime("x-special/gnome-copied-files")
+}
+
+pub fn kde_cut_selection() -> Target {
+    Target::mime("application/x-kde-cutselection")
+}
+
+/// Serves a file list as `text/uri-list`, `x-special/gnome-copied-files`,
+/// `application/x-kde-cutselection` and plain text paths.
+pub struct FilesProvider {
+    uri_list: Atom,
+    gnome_copied_files: Atom,
+    kde_cut_selection: Atom,
+    utf8_string: Atom,
+    files: FileList
+}
+
+impl FilesProvider {
+    /// Fails with `Error::RelativePath` unless all paths are absolute,
+    /// as `file://` URIs can't express relative ones.
+    pub fn new(context: &Context, files: FileList) -> Result<Self, Error> {
+        if let Some(path) = files.paths.iter().find(|path| !path.is_absolute()) {
+            return Err(Error::RelativePath(path.clone()));
+        }
+
+        Ok(FilesProvider {
+            uri_list: uri_list().atom(context)?,
+            gnome_copied_files: gnome_copied_files().atom(context)?,
+            kde_cut_selection: kde_cut_selection().atom(context)?,
+            utf8_string: context.atoms.utf8_string,
+            files
+        })
+    }
+}
+
+impl Provider for FilesProvider {
+    fn targets(&self) -> Vec<Atom> {
+        vec![self.uri_list, self.gnome_copied_files, self.kde_cut_selection, self.utf8_string]
+    }
+
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        let uris = self.files.paths.iter().map(|path| encode_uri(path));
+
+        if target == self.uri_list {
+            Some(uris.map(|uri| uri + "\r\n").collect::<String>().into_bytes())
+        } else if target == self.gnome_copied_files {
+            let operation = match self.files.operation {
+                FileOperation::Copy => "copy",
+                FileOperation::Cut => "cut"
+            };
+            let lines = Some(operation.to_owned()).into_iter().chain(uris);
+            Some(lines.collect::<Vec<_>>().join("\n").into_bytes())
+        } else if target == self.kde_cut_selection {
+            Some(match self.files.operation {
+                FileOperation::Copy => b"0".to_vec(),
+                FileOperation::Cut => b"1".to_vec()
+            })
+        } else if target == self.utf8_string {
+            let paths = self.files.paths.iter()
+                .map(|path| path.to_string_lossy())
+                .collect::<Vec<_>>();
+            Some(paths.join("\n").into_bytes())
+        } else {
+            None
+        }
+    }
+}
+
+/// Encode a path as a `file://` URI, percent-encoding all but the unreserved characters.
+pub fn encode_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(b as char),
+            _ => uri.push_str(&format!("%{:02X}", b))
+        }
+    }
+    uri
+}
+
+/// Decode a local `file://` URI, `None` for other schemes and remote hosts.
+pub fn decode_uri(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = match rest.find('/') {
+        Some(0) => rest,
+        Some(i) if &rest[..i] == "localhost" => &rest[i..],
+        _ => return None
+    };
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = ::std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+/// Decode a `text/uri-list` value, skipping comments and URIs that are not local files.
+pub fn decode_uri_list(value: &[u8]) -> Vec<PathBuf> {
+    String::from_utf8_lossy(value)
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(decode_uri)
+        .collect()
+}
+
+/// Decode a `x-special/gnome-copied-files` value.
+///
+/// Files are assumed to be copied when the first line names no operation.
+pub fn decode_gnome_copied_files(value: &[u8]) -> FileList {
+    let value = String::from_utf8_lossy(value);
+    let operation = match value.lines().next() {
+        Some("cut") => FileOperation::Cut,
+        _ => FileOperation::Copy
+    };
+    let paths = value.lines()
+        .filter_map(decode_uri)
+        .collect();
+    FileList { operation, paths }
+}
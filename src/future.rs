@@ -0,0 +1,230 @@
+use std::mem;
+use std::pin::Pin;
+use std::future::Future;
+use std::time::Duration;
+use std::sync::{ Mutex, PoisonError };
+use std::task::{ Context as TaskContext, Poll, Waker };
+use std::os::unix::io::{ AsFd, AsRawFd, BorrowedFd, RawFd };
+use async_io::{ Async, Timer };
+use xcb::{ self, Atom, Timestamp };
+use error::Error;
+use ::{ Clipboard, LoadState, Target };
+
+
+struct Fd(RawFd);
+
+impl AsFd for Fd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // the fd is owned by the connection, which outlives the future.
+        unsafe { BorrowedFd::borrow_raw(self.0) }
+    }
+}
+
+/// Lets one `LoadFuture` of a clipboard at a time use the getter connection,
+/// as concurrent futures would consume each other's events.
+#[derive(Default)]
+pub(crate) struct LoadLock {
+    /// Whether the lock is held, and the futures waiting for it.
+    state: Mutex<(bool, Vec<Waker>)>
+}
+
+impl LoadLock {
+    /// take the lock, or have `waker` woken once it is released.
+    fn try_lock(&self, waker: &Waker) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if !state.0 {
+            state.0 = true;
+            true
+        } else {
+            if !state.1.iter().any(|w| w.will_wake(waker)) {
+                state.1.push(waker.clone());
+            }
+            false
+        }
+    }
+
+    fn unlock(&self) {
+        let waiting = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.0 = false;
+            mem::take(&mut state.1)
+        };
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by `Clipboard::load_async`.
+///
+/// The futures of a clipboard run one at a time: each waits for the previous one
+/// to complete or be dropped before it starts.
+///
+/// Once started, the future asks the server for a timestamp by changing a property,
+/// and sends the conversion once the change is reported. In between and after that,
+/// it only wakes up when the connection file descriptor becomes readable.
+#[must_use = "futures do nothing unless polled"]
+pub struct LoadFuture<'a> {
+    clipboard: &'a Clipboard,
+    state: LoadState<Vec<u8>>,
+    target: Target,
+    /// The time of the conversion, `None` until the server reported it.
+    time: Option<Timestamp>,
+    timeout: Option<Duration>,
+    timer: Option<Timer>,
+    fd: Option<Async<Fd>>,
+    /// Whether this future holds the `LoadLock` of the clipboard.
+    locked: bool
+}
+
+impl<'a> LoadFuture<'a> {
+    pub(crate) fn new(clipboard: &'a Clipboard, selection: Atom, target: Target, property: Atom, timeout: Option<Duration>)
+        -> LoadFuture<'a>
+    {
+        LoadFuture {
+            clipboard,
+            // the target is interned on first poll, which may fail.
+            state: LoadState::new(selection, xcb::ATOM_NONE, property, Vec::new(), clipboard.max_size()),
+            target,
+            time: None,
+            timeout,
+            timer: None,
+            fd: None,
+            locked: false
+        }
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        let getter = &self.clipboard.getter;
+        let fd = Async::new(Fd(getter.connection.as_raw_fd()))
+            .map_err(|_| Error::IoError)?;
+        self.state.target = self.target.atom(getter)?;
+
+        // A zero-length change, only to be told the server time.
+        xcb::change_property(
+            &getter.connection, xcb::PROP_MODE_REPLACE as u8,
+            getter.window, self.state.property, xcb::ATOM_INTEGER, 32,
+            &[0u32; 0]
+        );
+        getter.connection.flush();
+
+        self.fd = Some(fd);
+        Ok(())
+    }
+
+    /// Send the conversion once `event` reports the time.
+    fn stamp(&mut self, event: &xcb::GenericEvent) {
+        let getter = &self.clipboard.getter;
+        if event.response_type() & !0x80 != xcb::PROPERTY_NOTIFY {
+            return;
+        }
+
+        let event = unsafe { xcb::cast_event::<xcb::PropertyNotifyEvent>(event) };
+        if event.window() != getter.window
+            || event.atom() != self.state.property
+            || event.state() != xcb::PROPERTY_NEW_VALUE as u8
+        {
+            return;
+        }
+
+        // The property is deleted before the owner can answer the conversion.
+        xcb::delete_property(&getter.connection, getter.window, self.state.property);
+        xcb::convert_selection(
+            &getter.connection, getter.window,
+            self.state.selection, self.state.target, self.state.property,
+            event.time()
+        );
+        getter.connection.flush();
+        self.time = Some(event.time());
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let getter = &self.clipboard.getter;
+        xcb::delete_property(&getter.connection, getter.window, self.state.property);
+        getter.connection.flush();
+        mem::take(&mut self.state.sink)
+    }
+
+    /// Let the next future use the connection.
+    fn release(&mut self) {
+        // The fd must be deregistered before another future registers it.
+        self.fd = None;
+        self.timer = None;
+        if self.locked {
+            self.locked = false;
+            self.clipboard.loading.unlock();
+        }
+    }
+
+    fn poll_load(&mut self, cx: &mut TaskContext) -> Poll<Result<Vec<u8>, Error>> {
+        if self.timer.is_none() {
+            self.timer = self.timeout.map(Timer::after);
+        }
+
+        if !self.locked {
+            if !self.clipboard.loading.try_lock(cx.waker()) {
+                if let Some(ref mut timer) = self.timer {
+                    if Pin::new(timer).poll(cx).is_ready() {
+                        return Poll::Ready(Err(Error::Timeout));
+                    }
+                }
+                return Poll::Pending;
+            }
+            self.locked = true;
+            self.start()?;
+        }
+
+        loop {
+            while let Some(event) = self.clipboard.getter.connection.poll_for_event() {
+                if self.time.is_none() {
+                    self.stamp(&event);
+                    continue
+                }
+
+                if self.clipboard.handle_event(&mut self.state, &event)? {
+                    return Poll::Ready(Ok(self.finish()));
+                }
+            }
+
+            if self.clipboard.getter.connection.has_error().is_err() {
+                return Poll::Ready(Err(Error::IoError));
+            }
+
+            if let Some(ref mut timer) = self.timer {
+                if Pin::new(timer).poll(cx).is_ready() {
+                    return Poll::Ready(Err(Error::Timeout));
+                }
+            }
+
+            let fd = match self.fd {
+                Some(ref fd) => fd,
+                None => return Poll::Ready(Err(Error::IoError))
+            };
+            match fd.poll_readable(cx) {
+                Poll::Ready(Ok(())) => continue,
+                Poll::Ready(Err(_)) => return Poll::Ready(Err(Error::IoError)),
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
+
+impl<'a> Drop for LoadFuture<'a> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl<'a> Future for LoadFuture<'a> {
+    type Output = Result<Vec<u8>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        let poll = this.poll_load(cx);
+        if poll.is_ready() {
+            this.release();
+        }
+        poll
+    }
+}
@@ -0,0 +1,193 @@
+//! HTML values, served together with a plain-text rendering.
+
+use xcb::Atom;
+use error::Error;
+use provider::Provider;
+use text::TextProvider;
+use { Context, Target };
+
+/// Elements that start a new line when rendered as text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt",
+    "figcaption", "figure", "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header",
+    "hr", "li", "main", "nav", "ol", "p", "pre", "section", "table", "tr", "ul"
+];
+
+/// Elements whose content is not text.
+const HIDDEN_ELEMENTS: &[&str] = &[ "head", "script", "style", "template", "title" ];
+
+/// The result of `Clipboard::load_html`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Content {
+    Html(String),
+    /// The owner offers no HTML, only text.
+    Text(String)
+}
+
+/// Serves HTML as `text/html`, along with its text rendering as the text targets.
+pub struct HtmlProvider {
+    html_atom: Atom,
+    html: String,
+    text: TextProvider
+}
+
+impl HtmlProvider {
+    pub fn new<S: Into<String>>(context: &Context, html: S) -> Result<Self, Error> {
+        let html = html.into();
+        let text = TextProvider::new(&context.atoms, to_text(&html));
+        Ok(HtmlProvider { html_atom: Target::text_html().atom(context)?, html, text })
+    }
+}
+
+impl Provider for HtmlProvider {
+    fn targets(&self) -> Vec<Atom> {
+        let mut targets = vec![self.html_atom];
+        targets.extend(self.text.targets());
+        targets
+    }
+
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        if target == self.html_atom {
+            Some(self.html.as_bytes().to_vec())
+        } else {
+            self.text.convert(target)
+        }
+    }
+
+    fn type_of(&self, target: Atom) -> Atom {
+        if target == self.html_atom {
+            target
+        } else {
+            self.text.type_of(target)
+        }
+    }
+}
+
+/// Decode a `text/html` value.
+///
+/// Some browsers send UTF-16 with a byte order mark, anything else must be UTF-8.
+pub fn decode(value: &[u8]) -> Result<String, Error> {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units = bytes.chunks_exact(2)
+            .map(|pair| from([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16_lossy(&units)
+    };
+
+    let mut html = match value {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => String::from_utf8(value.to_vec())?
+    };
+
+    let len = html.trim_end_matches('\0').len();
+    html.truncate(len);
+    Ok(html)
+}
+
+/// Render HTML as plain text.
+///
+/// Tags are stripped, entities decoded and whitespace collapsed,
+/// with block elements on lines of their own.
+pub fn to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' if rest.starts_with("<!--") => {
+                rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            },
+            '<' if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') => {
+                let end = match rest.find('>') {
+                    Some(end) => end,
+                    None => break
+                };
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+
+                let name = tag.trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or("")
+                    .to_ascii_lowercase();
+
+                if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    push_newline(&mut text);
+                } else if HIDDEN_ELEMENTS.contains(&name.as_str()) && !tag.starts_with('/') {
+                    let close = format!("</{}", name);
+                    rest = match rest.to_ascii_lowercase().find(&close) {
+                        Some(start) => &rest[start..],
+                        None => ""
+                    };
+                }
+            },
+            '&' => {
+                let (decoded, len) = decode_entity(rest).unwrap_or(('&', 1));
+                push_char(&mut text, decoded);
+                rest = &rest[len..];
+            },
+            c => {
+                push_char(&mut text, c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    let len = text.trim_end().len();
+    text.truncate(len);
+    text
+}
+
+fn push_char(text: &mut String, c: char) {
+    if !c.is_whitespace() || c == '\u{a0}' {
+        text.push(c);
+    } else if !(text.is_empty() || text.ends_with(' ') || text.ends_with('\n')) {
+        text.push(' ');
+    }
+}
+
+fn push_newline(text: &mut String) {
+    if text.ends_with(' ') {
+        text.pop();
+    }
+    if !(text.is_empty() || text.ends_with('\n')) {
+        text.push('\n');
+    }
+}
+
+/// Decode the entity at the start of `input`, with the length it takes up.
+fn decode_entity(input: &str) -> Option<(char, usize)> {
+    let end = input.char_indices()
+        .take(12)
+        .find(|&(_, c)| c == ';')
+        .map(|(i, _)| i)?;
+    let name = &input[1..end];
+
+    let c = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        ::std::char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+    } else if let Some(dec) = name.strip_prefix('#') {
+        ::std::char::from_u32(dec.parse().ok()?)?
+    } else {
+        match name {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            "nbsp" => '\u{a0}',
+            "copy" => '\u{a9}',
+            "reg" => '\u{ae}',
+            "trade" => '\u{2122}',
+            "hellip" => '\u{2026}',
+            "mdash" => '\u{2014}',
+            "ndash" => '\u{2013}',
+            "lsquo" => '\u{2018}',
+            "rsquo" => '\u{2019}',
+            "ldquo" => '\u{201c}',
+            "rdquo" => '\u{201d}',
+            _ => return None
+        }
+    };
+    Some((c, end + 1))
+}
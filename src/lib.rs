@@ -1,29 +1,75 @@
 pub extern crate xcb;
+extern crate libc;
+#[cfg(feature = "async")]
+extern crate async_io;
+#[cfg(feature = "image")]
+pub extern crate image;
 
 pub mod error;
+pub mod files;
+pub mod html;
+pub mod provider;
+pub mod text;
+#[cfg(feature = "image")]
+pub mod picture;
+mod owner;
+mod selection;
 mod run;
+mod watch;
+#[cfg(feature = "async")]
+mod future;
 
-use std::thread;
+use std::{ cmp, io, thread };
+use std::io::{ Read, Seek, Write };
+use std::os::unix::io::AsRawFd;
 use std::time::{ Duration, Instant };
-use std::sync::{ Arc, RwLock };
-use std::sync::mpsc::{ Sender, channel };
+use std::sync::{ Arc, Mutex, RwLock };
+use std::sync::atomic::{ AtomicU64, AtomicUsize, Ordering };
+use std::sync::mpsc::{ Sender, Receiver, channel };
 use std::collections::HashMap;
-use xcb::{ Connection, Window, Atom };
+use xcb::{ Connection, Window, Atom, Timestamp };
 use xcb::base::ConnError;
 use error::Error;
+use provider::{ Provider, ReaderProvider };
+use text::TextProvider;
+use files::{ FileList, FileOperation, FilesProvider };
+use html::HtmlProvider;
+
+pub use watch::{ SelectionWatcher, SelectionEvent, ChangeKind };
+pub use owner::OwnerInfo;
+pub use selection::{ Selection, Target };
+#[cfg(feature = "async")]
+pub use future::LoadFuture;
+#[cfg(feature = "async")]
+use future::LoadLock;
 
 pub const INCR_CHUNK_SIZE: usize = 4000;
 const POLL_DURATION: u64 = 50;
-type SetMap = Arc<RwLock<HashMap<Atom, (Atom, Vec<u8>)>>>;
+/// How long a requestor may take to delete the property before its transfer is dropped.
+const INCR_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the server to report the time of a property change.
+const SERVER_TIME_TIMEOUT: Duration = Duration::from_secs(3);
+type SetMap = Arc<RwLock<HashMap<Atom, (Timestamp, Box<dyn Provider>)>>>;
+type LostSenders = Arc<Mutex<Vec<Sender<SelectionLost>>>>;
+/// The INCR timeout of the owner thread, in milliseconds.
+type IncrTimeout = Arc<AtomicU64>;
 
 #[derive(Clone, Debug)]
 pub struct Atoms {
     pub primary: Atom,
+    pub secondary: Atom,
     pub clipboard: Atom,
     pub property: Atom,
     pub targets: Atom,
+    pub multiple: Atom,
+    pub atom_pair: Atom,
+    pub timestamp: Atom,
+    pub clipboard_manager: Atom,
+    pub save_targets: Atom,
     pub string: Atom,
     pub utf8_string: Atom,
+    pub text: Atom,
+    pub compound_text: Atom,
     pub incr: Atom
 }
 
@@ -31,10 +77,26 @@ pub struct Atoms {
 pub struct Clipboard {
     pub getter: Context,
     pub setter: Arc<Context>,
+    /// Only used to obtain server timestamps, so that waiting for one
+    /// never consumes the events of a concurrent load.
+    stamp: Mutex<Context>,
     setmap: SetMap,
+    lost: LostSenders,
+    max_size: AtomicUsize,
+    incr_timeout: IncrTimeout,
+    #[cfg(feature = "async")]
+    loading: LoadLock,
     send: Sender<Atom>
 }
 
+/// A stored selection was taken over by another client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelectionLost {
+    pub selection: Selection,
+    /// The time at which the new owner acquired the selection.
+    pub time: Timestamp
+}
+
 pub struct Context {
     pub connection: Connection,
     pub screen: i32,
@@ -42,6 +104,39 @@ pub struct Context {
     pub atoms: Atoms
 }
 
+struct LoadState<W> {
+    selection: Atom,
+    target: Atom,
+    property: Atom,
+    is_incr: bool,
+    type_: Atom,
+    sink: W,
+    received: usize,
+    size: Option<usize>,
+    limit: Option<usize>
+}
+
+impl<W: Write> LoadState<W> {
+    fn new(selection: Atom, target: Atom, property: Atom, sink: W, limit: Option<usize>) -> LoadState<W> {
+        LoadState { selection, target, property, is_incr: false, type_: xcb::ATOM_NONE, sink, received: 0, size: None, limit }
+    }
+
+    /// fail if receiving `len` more bytes would exceed the limit.
+    fn check(&self, len: usize) -> Result<(), Error> {
+        match self.limit {
+            Some(limit) if self.received.saturating_add(len) > limit => Err(Error::TooLarge(limit)),
+            _ => Ok(())
+        }
+    }
+
+    fn write(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.check(chunk.len())?;
+        self.sink.write_all(chunk).map_err(Error::Write)?;
+        self.received += chunk.len();
+        Ok(())
+    }
+}
+
 #[inline]
 fn get_atom(connection: &Connection, name: &str) -> Result<Atom, Error> {
     xcb::intern_atom(connection, false, name)
@@ -50,6 +145,29 @@ fn get_atom(connection: &Connection, name: &str) -> Result<Atom, Error> {
         .map_err(Into::into)
 }
 
+/// wait until the connection has data to read, for at most `timeout`.
+///
+/// Events already queued by xcb are not seen, so call `poll_for_event` first.
+/// Returns `false` once the timeout expired, and may return `true` spuriously.
+fn wait_readable(connection: &Connection, timeout: Option<Duration>) -> Result<bool, Error> {
+    let mut fd = libc::pollfd {
+        fd: connection.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0
+    };
+    // Round up, so that a timeout below one millisecond doesn't spin.
+    let timeout = timeout
+        .map(|timeout| cmp::min(timeout.as_nanos().div_ceil(1_000_000), i32::MAX as u128) as libc::c_int)
+        .unwrap_or(-1);
+
+    match unsafe { libc::poll(&mut fd, 1, timeout) } {
+        0 => Ok(false),
+        n if n > 0 => Ok(true),
+        _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => Ok(true),
+        _ => Err(Error::IoError)
+    }
+}
+
 impl Context {
     pub fn new(displayname: Option<&str>) -> Result<Self, Error> {
         let (connection, screen) = Connection::connect(displayname)?;
@@ -82,11 +200,19 @@ impl Context {
 
         let atoms = Atoms {
             primary: xcb::ATOM_PRIMARY,
+            secondary: xcb::ATOM_SECONDARY,
             clipboard: intern_atom!("CLIPBOARD"),
             property: intern_atom!("THIS_CLIPBOARD_OUT"),
             targets: intern_atom!("TARGETS"),
+            multiple: intern_atom!("MULTIPLE"),
+            atom_pair: intern_atom!("ATOM_PAIR"),
+            timestamp: intern_atom!("TIMESTAMP"),
+            clipboard_manager: intern_atom!("CLIPBOARD_MANAGER"),
+            save_targets: intern_atom!("SAVE_TARGETS"),
             string: xcb::ATOM_STRING,
             utf8_string: intern_atom!("UTF8_STRING"),
+            text: intern_atom!("TEXT"),
+            compound_text: intern_atom!("COMPOUND_TEXT"),
             incr: intern_atom!("INCR")
         };
 
@@ -104,163 +230,474 @@ impl Clipboard {
     pub fn new() -> Result<Self, Error> {
         let getter = Context::new(None)?;
         let setter = Arc::new(Context::new(None)?);
+        let stamp = Mutex::new(Context::new(None)?);
         let setter2 = Arc::clone(&setter);
         let setmap = Arc::new(RwLock::new(HashMap::new()));
         let setmap2 = Arc::clone(&setmap);
+        let lost = Arc::new(Mutex::new(Vec::new()));
+        let lost2 = Arc::clone(&lost);
+        let incr_timeout = Arc::new(AtomicU64::new(INCR_TIMEOUT.as_millis() as u64));
+        let incr_timeout2 = Arc::clone(&incr_timeout);
 
         let (sender, receiver) = channel();
         let max_length = setter.connection.get_maximum_request_length() as usize * 4;
-        thread::spawn(move || run::run(&setter2, &setmap2, max_length, &receiver));
+        thread::spawn(move || run::run(&setter2, &setmap2, &lost2, &incr_timeout2, max_length, &receiver));
+
+        Ok(Clipboard {
+            getter, setter, stamp, setmap, lost,
+            max_size: AtomicUsize::new(usize::MAX),
+            incr_timeout,
+            #[cfg(feature = "async")]
+            loading: LoadLock::default(),
+            send: sender
+        })
+    }
+
+    /// limit the size of each loaded value, `None` to accept values of any size.
+    ///
+    /// A load whose value is larger fails with `Error::TooLarge`, both for direct
+    /// and incremental transfers, before the excess is received.
+    pub fn set_max_size(&self, max_size: Option<usize>) {
+        self.max_size.store(max_size.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// the current limit on the size of each loaded value.
+    pub fn max_size(&self) -> Option<usize> {
+        match self.max_size.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            max_size => Some(max_size)
+        }
+    }
+
+    /// set how long a requestor of a stored value may take to delete the property
+    /// during an incremental transfer, before the transfer is dropped.
+    ///
+    /// Defaults to 5 seconds.
+    pub fn set_incr_timeout(&self, timeout: Duration) {
+        let millis = cmp::min(timeout.as_millis(), u128::from(u64::MAX)) as u64;
+        self.incr_timeout.store(millis, Ordering::Relaxed);
+    }
+
+    /// the current timeout of incremental transfers.
+    pub fn incr_timeout(&self) -> Duration {
+        Duration::from_millis(self.incr_timeout.load(Ordering::Relaxed))
+    }
+
+    /// get the current server time with a zero-length property change.
+    fn server_time(&self) -> Result<Timestamp, Error> {
+        let stamp = self.stamp.lock().map_err(|_| Error::Lock)?;
+        let property = stamp.atoms.property;
+        let start_time = Instant::now();
+
+        xcb::change_property(
+            &stamp.connection, xcb::PROP_MODE_REPLACE as u8,
+            stamp.window, property, xcb::ATOM_INTEGER, 32,
+            &[0u32; 0]
+        );
+        // The server queues the PropertyNotify before answering any later request,
+        // so after one round trip it is normally waiting in the queue.
+        xcb::get_input_focus(&stamp.connection).get_reply()?;
+
+        let time = loop {
+            let event = match stamp.connection.poll_for_event() {
+                Some(event) => event,
+                None => {
+                    stamp.connection.has_error().map_err(|_| Error::IoError)?;
+                    let remaining = SERVER_TIME_TIMEOUT.saturating_sub(start_time.elapsed());
+                    if !wait_readable(&stamp.connection, Some(remaining))? {
+                        return Err(Error::Timeout);
+                    }
+                    continue
+                }
+            };
+
+            if event.response_type() & !0x80 == xcb::PROPERTY_NOTIFY {
+                let event = unsafe { xcb::cast_event::<xcb::PropertyNotifyEvent>(&event) };
+                if event.window() == stamp.window
+                    && event.atom() == property
+                    && event.state() == xcb::PROPERTY_NEW_VALUE as u8
+                {
+                    break event.time();
+                }
+            }
+        };
+
+        xcb::delete_property(&stamp.connection, stamp.window, property);
+        stamp.connection.flush();
+        Ok(time)
+    }
 
-        Ok(Clipboard { getter, setter, setmap, send: sender })
+    fn is_expected_type(&self, target: Atom, type_: Atom) -> bool {
+        type_ == target
+            || (target == self.getter.atoms.targets && type_ == xcb::ATOM_ATOM)
+            || (target == self.getter.atoms.timestamp && type_ == xcb::ATOM_INTEGER)
+            || (target == self.getter.atoms.multiple && type_ == self.getter.atoms.atom_pair)
+            || target == self.getter.atoms.text
     }
 
-    fn process_event<T>(&self, buff: &mut Vec<u8>, selection: Atom, target: Atom, property: Atom, timeout: T, use_xfixes: bool, xfixes_event_base: u8)
+    fn process_event<W, T, F>(&self, state: &mut LoadState<W>, timeout: T, xfixes_event_base: Option<u8>, mut progress: F)
         -> Result<(), Error>
-        where T: Into<Option<Duration>>
+        where
+            W: Write,
+            T: Into<Option<Duration>>,
+            F: FnMut(usize, Option<usize>)
     {
-        let mut is_incr = false;
         let timeout = timeout.into();
-        let start_time =
-            if timeout.is_some() { Some(Instant::now()) }
-            else { None };
+        let start_time = Instant::now();
 
         loop {
-            if timeout.into_iter()
-                .zip(start_time)
-                .next()
-                .map(|(timeout, time)| (Instant::now() - time) >= timeout)
-                .unwrap_or(false)
-            {
+            let remaining = timeout.map(|timeout| timeout.saturating_sub(start_time.elapsed()));
+            if remaining == Some(Duration::from_secs(0)) {
                 return Err(Error::Timeout);
             }
 
-            let event = match use_xfixes {
-                true => {
-                    match self.getter.connection.wait_for_event() {
-                        Some(event) => event,
-                        None => {
-                            return Err(Error::IoError);
-                        }
-                    }
-                },
-                false => {
-                    match self.getter.connection.poll_for_event() {
-                        Some(event) => event,
-                        None => {
-                            thread::park_timeout(Duration::from_millis(POLL_DURATION));
-                            continue;
-                        }
-                    }
+            let event = match self.getter.connection.poll_for_event() {
+                Some(event) => event,
+                None => {
+                    self.getter.connection.has_error().map_err(|_| Error::IoError)?;
+                    wait_readable(&self.getter.connection, remaining)?;
+                    continue;
                 }
             };
 
             let r = event.response_type();
 
-            if use_xfixes && r == (xfixes_event_base + xcb::xfixes::SELECTION_NOTIFY) {
+            if xfixes_event_base.map(|base| r == base + xcb::xfixes::SELECTION_NOTIFY).unwrap_or(false) {
                 let event = unsafe { xcb::cast_event::<xcb::xfixes::SelectionNotifyEvent>(&event) };
                 xcb::convert_selection(&self.getter.connection, self.getter.window,
-                                       selection, target, property,
+                                       state.selection, state.target, state.property,
                                        event.timestamp());
                 self.getter.connection.flush();
                 continue;
             }
 
-            match r & !0x80 {
-                xcb::SELECTION_NOTIFY => {
-                    let event = unsafe { xcb::cast_event::<xcb::SelectionNotifyEvent>(&event) };
-                    if event.selection() != selection { continue };
+            let received = state.received;
+            let is_end = self.handle_event(state, &event)?;
+            if state.received != received {
+                progress(state.received, state.size);
+            }
+            if is_end {
+                break
+            }
+        }
+        Ok(())
+    }
 
-                    // Note that setting the property argument to None indicates that the
-                    // conversion requested could not be made.
-                    if event.property() == xcb::ATOM_NONE {
-                        break;
-                    }
+    /// handle one event of a conversion, returns `true` once the value is complete.
+    fn handle_event<W: Write>(&self, state: &mut LoadState<W>, event: &xcb::GenericEvent)
+        -> Result<bool, Error>
+    {
+        match event.response_type() & !0x80 {
+            xcb::SELECTION_NOTIFY => {
+                let event = unsafe { xcb::cast_event::<xcb::SelectionNotifyEvent>(event) };
+                if event.selection() != state.selection { return Ok(false) };
+
+                // Note that setting the property argument to None indicates that the
+                // conversion requested could not be made.
+                if event.property() == xcb::ATOM_NONE {
+                    return Ok(true);
+                }
 
-                    let reply =
-                        xcb::get_property(
-                            &self.getter.connection, false, self.getter.window,
-                            event.property(), xcb::ATOM_ANY, buff.len() as u32, ::std::u32::MAX // FIXME reasonable buffer size
-                        )
-                        .get_reply()?;
-
-                    if reply.type_() == self.getter.atoms.incr {
-                        if let Some(&size) = reply.value::<i32>().get(0) {
-                            buff.reserve(size as usize);
-                        }
-                        xcb::delete_property(&self.getter.connection, self.getter.window, property);
-                        self.getter.connection.flush();
-                        is_incr = true;
-                        continue
-                    } else if reply.type_() != target {
-                        return Err(Error::UnexpectedType(reply.type_()));
-                    }
+                // Fetch at most one word more than the limit, to notice larger values.
+                let long_length = state.limit
+                    .map(|limit| cmp::min(limit / 4 + 1, u32::MAX as usize) as u32)
+                    .unwrap_or(u32::MAX);
+                let reply =
+                    xcb::get_property(
+                        &self.getter.connection, false, self.getter.window,
+                        event.property(), xcb::ATOM_ANY, 0, long_length
+                    )
+                    .get_reply()?;
+
+                if reply.type_() == self.getter.atoms.incr {
+                    // The size is only a lower bound on the length of the value.
+                    state.size = reply.value::<u32>().first().map(|&size| size as usize);
+                    state.check(state.size.unwrap_or(0))?;
+                    xcb::delete_property(&self.getter.connection, self.getter.window, state.property);
+                    self.getter.connection.flush();
+                    state.is_incr = true;
+                    return Ok(false);
+                } else if !self.is_expected_type(state.target, reply.type_()) {
+                    return Err(Error::UnexpectedType(reply.type_()));
+                }
 
-                    buff.extend_from_slice(reply.value());
-                    break
-                },
-                xcb::PROPERTY_NOTIFY if is_incr => {
-                    let event = unsafe { xcb::cast_event::<xcb::PropertyNotifyEvent>(&event) };
-                    if event.state() != xcb::PROPERTY_NEW_VALUE as u8 { continue };
-
-                    let length =
-                        xcb::get_property(
-                            &self.getter.connection, false, self.getter.window,
-                            property, xcb::ATOM_ANY, 0, 0
-                        )
-                        .get_reply()
-                        .map(|reply| reply.bytes_after())?;
-
-                    let reply =
-                        xcb::get_property(
-                            &self.getter.connection, true, self.getter.window,
-                            property, xcb::ATOM_ANY, 0, length
-                        )
-                        .get_reply()?;
-
-                    if reply.type_() != target { continue };
-
-                    if reply.value_len() != 0 {
-                        buff.extend_from_slice(reply.value());
-                    } else {
-                        break
-                    }
-                },
-                _ => ()
-            }
+                state.check(reply.value::<u8>().len() + reply.bytes_after() as usize)?;
+                state.type_ = reply.type_();
+                state.write(reply.value())?;
+                Ok(true)
+            },
+            xcb::PROPERTY_NOTIFY if state.is_incr => {
+                let event = unsafe { xcb::cast_event::<xcb::PropertyNotifyEvent>(event) };
+                if event.state() != xcb::PROPERTY_NEW_VALUE as u8 { return Ok(false) };
+
+                let length =
+                    xcb::get_property(
+                        &self.getter.connection, false, self.getter.window,
+                        state.property, xcb::ATOM_ANY, 0, 0
+                    )
+                    .get_reply()
+                    .map(|reply| reply.bytes_after())?;
+                state.check(length as usize)?;
+
+                let reply =
+                    xcb::get_property(
+                        &self.getter.connection, true, self.getter.window,
+                        state.property, xcb::ATOM_ANY, 0, length
+                    )
+                    .get_reply()?;
+
+                if !self.is_expected_type(state.target, reply.type_()) { return Ok(false) };
+
+                if reply.value_len() != 0 {
+                    state.type_ = reply.type_();
+                    state.write(reply.value())?;
+                    Ok(false)
+                } else {
+                    Ok(true)
+                }
+            },
+            _ => Ok(false)
         }
-        Ok(())
     }
 
     /// load value.
-    pub fn load<T>(&self, selection: Atom, target: Atom, property: Atom, timeout: T)
+    pub fn load<T>(&self, selection: Selection, target: Target, property: Atom, timeout: T)
         -> Result<Vec<u8>, Error>
         where T: Into<Option<Duration>>
     {
-        let mut buff = Vec::new();
+        self.load_at(selection, target, property, None, timeout)
+    }
+
+    /// load value, as requested at `time`.
+    ///
+    /// Clients should not use CurrentTime for the time argument of a ConvertSelection request.
+    /// Instead, they should use the timestamp of the event that caused the request to be made,
+    /// such as a key or button press. Without one, a timestamp is obtained from the server.
+    pub fn load_at<T>(&self, selection: Selection, target: Target, property: Atom, time: Option<Timestamp>, timeout: T)
+        -> Result<Vec<u8>, Error>
+        where T: Into<Option<Duration>>
+    {
+        let selection = selection.atom(&self.getter.atoms);
+        let target = target.atom(&self.getter)?;
+        let mut state = LoadState::new(selection, target, property, Vec::new(), self.max_size());
+        self.convert(&mut state, time, timeout.into(), |_, _| ())?;
+        Ok(state.sink)
+    }
+
+    /// load value together with the type the owner converted it to.
+    fn load_typed<T>(&self, selection: Atom, target: Atom, property: Atom, timeout: T)
+        -> Result<(Atom, Vec<u8>), Error>
+        where T: Into<Option<Duration>>
+    {
+        let mut state = LoadState::new(selection, target, property, Vec::new(), self.max_size());
+        self.convert(&mut state, None, timeout.into(), |_, _| ())?;
+        Ok((state.type_, state.sink))
+    }
+
+    /// the index of the first of `preferred` that the owner offers.
+    fn negotiate(&self, selection: Selection, preferred: &[Target], timeout: Option<Duration>)
+        -> Result<Option<usize>, Error>
+    {
+        let available = self.available_targets(selection, timeout)?;
+
+        for (i, target) in preferred.iter().enumerate() {
+            let atom = target.atom(&self.getter)?;
+            if available.iter().any(|&(available, _)| available == atom) {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
+    /// load the first of `preferred` that the owner offers,
+    /// returned together with the chosen target.
+    ///
+    /// The targets are negotiated with a single `TARGETS` request,
+    /// and `Error::NoTarget` is returned if none of them is offered.
+    pub fn load_best<T>(&self, selection: Selection, preferred: &[Target], timeout: T)
+        -> Result<(Target, Vec<u8>), Error>
+        where T: Into<Option<Duration>>
+    {
+        let timeout = timeout.into();
+
+        let i = self.negotiate(selection, preferred, timeout)?
+            .ok_or(Error::NoTarget)?;
+        let target = preferred[i].clone();
+        let value = self.load(selection, target.clone(), self.getter.atoms.property, timeout)?;
+        Ok((target, value))
+    }
+
+    /// load text, decoded from whichever text target the owner supports best.
+    ///
+    /// Targets are preferred in the order `UTF8_STRING`, `COMPOUND_TEXT`, `STRING` and `TEXT`.
+    pub fn load_text<T>(&self, selection: Selection, timeout: T)
+        -> Result<String, Error>
+        where T: Into<Option<Duration>>
+    {
+        let atoms = &self.getter.atoms;
+        let timeout = timeout.into();
+
+        // The reply type is needed to decode `TEXT`, so only negotiate here.
+        let preferred = [Target::Utf8String, Target::CompoundText, Target::String, Target::Text];
+        let target = match self.negotiate(selection, &preferred, timeout)? {
+            Some(i) => preferred[i].atom(&self.getter)?,
+            // Fall back to UTF8_STRING for owners that don't answer TARGETS.
+            None => atoms.utf8_string
+        };
+        self.load_text_target(selection, target, timeout)
+    }
+
+    /// load `target` and decode it as text according to the reply type.
+    fn load_text_target(&self, selection: Selection, target: Atom, timeout: Option<Duration>)
+        -> Result<String, Error>
+    {
+        let atoms = &self.getter.atoms;
+        let selection = selection.atom(atoms);
+        let (type_, value) = self.load_typed(selection, target, atoms.property, timeout)?;
+        if type_ == xcb::ATOM_NONE {
+            return Ok(String::new());
+        }
+        text::decode(atoms, type_, &value)
+    }
+
+    /// load an image, decoded from whichever image target the owner offers.
+    ///
+    /// Targets are preferred in the order `image/png`, `image/bmp` and `image/jpeg`.
+    #[cfg(feature = "image")]
+    pub fn load_image<T>(&self, selection: Selection, timeout: T)
+        -> Result<image::RgbaImage, Error>
+        where T: Into<Option<Duration>>
+    {
+        let formats = picture::targets();
+        let preferred = formats.iter()
+            .map(|(target, _)| target.clone())
+            .collect::<Vec<_>>();
+
+        let (target, value) = self.load_best(selection, &preferred, timeout)?;
+        let format = formats.iter()
+            .find(|(format_target, _)| *format_target == target)
+            .map(|&(_, format)| format)
+            .ok_or(Error::NoTarget)?;
+        picture::decode(format, &value)
+    }
+
+    /// load a list of files, as copied or cut by a file manager.
+    ///
+    /// `x-special/gnome-copied-files` is preferred, as it tells copies and cuts apart,
+    /// otherwise the files of `text/uri-list` are assumed to be copied.
+    pub fn load_files<T>(&self, selection: Selection, timeout: T)
+        -> Result<FileList, Error>
+        where T: Into<Option<Duration>>
+    {
+        let preferred = [files::gnome_copied_files(), files::uri_list()];
+
+        let (target, value) = self.load_best(selection, &preferred, timeout)?;
+        if target == files::gnome_copied_files() {
+            Ok(files::decode_gnome_copied_files(&value))
+        } else {
+            Ok(FileList {
+                operation: FileOperation::Copy,
+                paths: files::decode_uri_list(&value)
+            })
+        }
+    }
+
+    /// load HTML if the owner offers `text/html`, and text otherwise.
+    pub fn load_html<T>(&self, selection: Selection, timeout: T)
+        -> Result<html::Content, Error>
+        where T: Into<Option<Duration>>
+    {
         let timeout = timeout.into();
 
+        // Negotiate HTML and the text fallbacks together, in a single TARGETS request.
+        let preferred = [
+            Target::text_html(),
+            Target::Utf8String, Target::CompoundText, Target::String, Target::Text
+        ];
+        let target = match self.negotiate(selection, &preferred, timeout)? {
+            Some(0) => {
+                let value = self.load(selection, Target::text_html(), self.getter.atoms.property, timeout)?;
+                return html::decode(&value).map(html::Content::Html);
+            },
+            Some(i) => preferred[i].atom(&self.getter)?,
+            None => self.getter.atoms.utf8_string
+        };
+        self.load_text_target(selection, target, timeout).map(html::Content::Text)
+    }
+
+    /// load the text of the clipboard, see `load_text`.
+    pub fn get_text<T>(&self, timeout: T)
+        -> Result<String, Error>
+        where T: Into<Option<Duration>>
+    {
+        self.load_text(Selection::Clipboard, timeout)
+    }
+
+    /// load value into `sink`, writing each chunk as soon as it arrives.
+    ///
+    /// `progress` is called after every chunk with the number of bytes received so far,
+    /// and the size announced by the owner for incremental transfers.
+    /// Returns the total number of bytes written.
+    pub fn load_into<W, F, T>(&self, selection: Selection, target: Target, property: Atom, sink: W, progress: F, timeout: T)
+        -> Result<usize, Error>
+        where
+            W: Write,
+            F: FnMut(usize, Option<usize>),
+            T: Into<Option<Duration>>
+    {
+        let selection = selection.atom(&self.getter.atoms);
+        let target = target.atom(&self.getter)?;
+        let mut state = LoadState::new(selection, target, property, sink, self.max_size());
+        self.convert(&mut state, None, timeout.into(), progress)?;
+        Ok(state.received)
+    }
+
+    fn convert<W, F>(&self, state: &mut LoadState<W>, time: Option<Timestamp>, timeout: Option<Duration>, progress: F)
+        -> Result<(), Error>
+        where
+            W: Write,
+            F: FnMut(usize, Option<usize>)
+    {
+        let time = match time {
+            Some(time) => time,
+            None => self.server_time()?
+        };
+
         xcb::convert_selection(
             &self.getter.connection, self.getter.window,
-            selection, target, property,
-            xcb::CURRENT_TIME
-                // FIXME ^
-                // Clients should not use CurrentTime for the time argument of a ConvertSelection request.
-                // Instead, they should use the timestamp of the event that caused the request to be made.
+            state.selection, state.target, state.property,
+            time
         );
         self.getter.connection.flush();
 
-        self.process_event(&mut buff, selection, target, property, timeout, false, 0)?;
-        xcb::delete_property(&self.getter.connection, self.getter.window, property);
+        self.process_event(state, timeout, None, progress)?;
+        xcb::delete_property(&self.getter.connection, self.getter.window, state.property);
         self.getter.connection.flush();
-        Ok(buff)
+        Ok(())
+    }
+
+    /// load value asynchronously.
+    ///
+    /// Unlike `load`, this does not poll: the returned future is woken up by
+    /// readiness of the connection file descriptor, so it can be awaited from
+    /// any runtime, such as tokio or async-std.
+    ///
+    /// Concurrent futures of the same clipboard take turns, as they share its connection.
+    #[cfg(feature = "async")]
+    pub fn load_async<T>(&self, selection: Selection, target: Target, property: Atom, timeout: T)
+        -> LoadFuture<'_>
+        where T: Into<Option<Duration>>
+    {
+        LoadFuture::new(self, selection.atom(&self.getter.atoms), target, property, timeout.into())
     }
 
     /// wait for a new value and load it
-    pub fn load_wait(&self, selection: Atom, target: Atom, property: Atom)
+    pub fn load_wait(&self, selection: Selection, target: Target, property: Atom)
         -> Result<Vec<u8>, Error>
     {
-        let mut buff = Vec::new();
+        let selection = selection.atom(&self.getter.atoms);
+        let target = target.atom(&self.getter)?;
+        let mut state = LoadState::new(selection, target, property, Vec::new(), self.max_size());
 
         let screen = &self.getter.connection.get_setup().roots()
             .nth(self.getter.screen as usize)
@@ -268,14 +705,12 @@ impl Clipboard {
 
         let xfixes = xcb::query_extension(
             &self.getter.connection, "XFIXES").get_reply()?;
-        assert!(xfixes.present());
+        if !xfixes.present() {
+            return Err(Error::MissingExtension("XFIXES"));
+        }
         xcb::xfixes::query_version(&self.getter.connection, 5, 0);
-        // Clear selection sources...
-        xcb::xfixes::select_selection_input(
-            &self.getter.connection, screen.root(), self.getter.atoms.primary, 0);
-        xcb::xfixes::select_selection_input(
-            &self.getter.connection, screen.root(), self.getter.atoms.clipboard, 0);
-        // ...and set the one requested now
+        // Only select the requested selection, so that waiting on it
+        // does not disturb watches of other selections...
         xcb::xfixes::select_selection_input(
             &self.getter.connection, screen.root(), selection,
             xcb::xfixes::SELECTION_EVENT_MASK_SET_SELECTION_OWNER |
@@ -283,26 +718,129 @@ impl Clipboard {
             xcb::xfixes::SELECTION_EVENT_MASK_SELECTION_WINDOW_DESTROY);
         self.getter.connection.flush();
 
-        self.process_event(&mut buff, selection, target, property, None, true, xfixes.first_event())?;
+        let result = self.process_event(&mut state, None, Some(xfixes.first_event()), |_, _| ());
+
+        // ...and clear it again once done.
+        xcb::xfixes::select_selection_input(
+            &self.getter.connection, screen.root(), selection, 0);
         xcb::delete_property(&self.getter.connection, self.getter.window, property);
         self.getter.connection.flush();
-        Ok(buff)
+        result.map(|_| state.sink)
+    }
+
+    /// query the targets offered by the current owner of the selection.
+    ///
+    /// Each target is returned together with its atom name.
+    pub fn available_targets<T>(&self, selection: Selection, timeout: T)
+        -> Result<Vec<(Atom, String)>, Error>
+        where T: Into<Option<Duration>>
+    {
+        let buff = self.load(selection, Target::Targets, self.getter.atoms.property, timeout)?;
+
+        let cookies = buff.chunks_exact(4)
+            .map(|chunk| Atom::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .filter(|&atom| atom != xcb::ATOM_NONE)
+            .map(|atom| (atom, xcb::get_atom_name(&self.getter.connection, atom)))
+            .collect::<Vec<_>>();
+
+        cookies.into_iter()
+            .map(|(atom, cookie)| {
+                let reply = cookie.get_reply()?;
+                Ok((atom, reply.name().to_owned()))
+            })
+            .collect()
     }
 
     /// store value.
-    pub fn store<T: Into<Vec<u8>>>(&self, selection: Atom, target: Atom, value: T)
+    pub fn store<T: Into<Vec<u8>>>(&self, selection: Selection, target: Target, value: T)
+        -> Result<(), Error>
+    {
+        self.store_targets(selection, Some((target, value)))
+    }
+
+    /// store several values at once, one for each target.
+    ///
+    /// All targets are advertised in reply to `TARGETS`,
+    /// and each requestor receives the value of the target it asked for.
+    pub fn store_targets<I, T>(&self, selection: Selection, targets: I)
+        -> Result<(), Error>
+        where
+            I: IntoIterator<Item = (Target, T)>,
+            T: Into<Vec<u8>>
+    {
+        let targets = targets.into_iter()
+            .map(|(target, value)| Ok((target.atom(&self.setter)?, value.into())))
+            .collect::<Result<Vec<(Atom, Vec<u8>)>, Error>>()?;
+
+        self.store_provider(selection, targets)
+    }
+
+    /// store text, served as `UTF8_STRING`, `COMPOUND_TEXT`, `STRING` and `TEXT`.
+    pub fn store_text<S: Into<String>>(&self, selection: Selection, text: S)
+        -> Result<(), Error>
+    {
+        self.store_provider(selection, TextProvider::new(&self.setter.atoms, text))
+    }
+
+    /// store an RGBA image, served as `image/png`, `image/bmp` and `image/jpeg`.
+    #[cfg(feature = "image")]
+    pub fn store_image(&self, selection: Selection, image: image::RgbaImage)
+        -> Result<(), Error>
+    {
+        self.store_provider(selection, picture::ImageProvider::new(&self.setter, image)?)
+    }
+
+    /// store a list of files, served in the formats file managers understand.
+    pub fn store_files(&self, selection: Selection, files: FileList)
+        -> Result<(), Error>
+    {
+        self.store_provider(selection, FilesProvider::new(&self.setter, files)?)
+    }
+
+    /// store HTML, served as `text/html` and as text with the markup stripped.
+    pub fn store_html<S: Into<String>>(&self, selection: Selection, html: S)
         -> Result<(), Error>
     {
+        self.store_provider(selection, HtmlProvider::new(&self.setter, html)?)
+    }
+
+    /// store text in the clipboard, see `store_text`.
+    pub fn set_text<S: Into<String>>(&self, text: S)
+        -> Result<(), Error>
+    {
+        self.store_text(Selection::Clipboard, text)
+    }
+
+    /// store a value read from a seekable source, such as a file.
+    ///
+    /// The source is read chunk by chunk while serving incremental transfers,
+    /// so large values are never copied into memory as a whole.
+    pub fn store_reader<R>(&self, selection: Selection, target: Target, source: R)
+        -> Result<(), Error>
+        where R: Read + Seek + Send + 'static
+    {
+        let target = target.atom(&self.setter)?;
+        self.store_provider(selection, ReaderProvider::new(target, source))
+    }
+
+    /// store a provider that produces the value when it is requested.
+    pub fn store_provider<P>(&self, selection: Selection, provider: P)
+        -> Result<(), Error>
+        where P: Provider + 'static
+    {
+        let selection = selection.atom(&self.setter.atoms);
+        let time = self.server_time()?;
+
         self.send.send(selection)?;
         self.setmap
             .write()
             .map_err(|_| Error::Lock)?
-            .insert(selection, (target, value.into()));
+            .insert(selection, (time, Box::new(provider)));
 
         xcb::set_selection_owner(
             &self.setter.connection,
             self.setter.window, selection,
-            xcb::CURRENT_TIME
+            time
         );
 
         self.setter.connection.flush();
@@ -317,4 +855,96 @@ impl Clipboard {
             Err(Error::Owner)
         }
     }
+
+    /// the time at which the selection was acquired by `store`,
+    /// or `None` if the selection is not owned.
+    pub fn ownership_timestamp(&self, selection: Selection)
+        -> Result<Option<Timestamp>, Error>
+    {
+        let selection = selection.atom(&self.setter.atoms);
+        let read_map = self.setmap
+            .read()
+            .map_err(|_| Error::Lock)?;
+        Ok(read_map.get(&selection).map(|&(time, _)| time))
+    }
+
+    /// get notified each time a stored selection is taken over by another client.
+    pub fn ownership_lost(&self) -> Result<Receiver<SelectionLost>, Error> {
+        let (sender, receiver) = channel();
+        self.lost
+            .lock()
+            .map_err(|_| Error::Lock)?
+            .push(sender);
+        Ok(receiver)
+    }
+
+    /// hand the clipboard contents over to the clipboard manager.
+    ///
+    /// This blocks until the manager has fetched the data,
+    /// so that it survives after the clipboard is dropped.
+    pub fn persist<T>(&self, timeout: T) -> Result<(), Error>
+        where T: Into<Option<Duration>>
+    {
+        let selection = self.getter.atoms.clipboard;
+        let property = self.getter.atoms.property;
+        let timeout = timeout.into();
+        let start_time = Instant::now();
+
+        let (time, targets) = {
+            let read_map = self.setmap
+                .read()
+                .map_err(|_| Error::Lock)?;
+            match read_map.get(&selection) {
+                Some(&(time, ref provider)) => (time, provider.targets()),
+                None => return Ok(())
+            }
+        };
+
+        let manager = xcb::get_selection_owner(&self.getter.connection, self.getter.atoms.clipboard_manager)
+            .get_reply()?
+            .owner();
+        if manager == xcb::NONE {
+            return Err(Error::NoManager);
+        }
+
+        xcb::change_property(
+            &self.getter.connection, xcb::PROP_MODE_REPLACE as u8,
+            self.getter.window, property, xcb::ATOM_ATOM, 32,
+            &targets
+        );
+        xcb::convert_selection(
+            &self.getter.connection, self.getter.window,
+            self.getter.atoms.clipboard_manager, self.getter.atoms.save_targets, property,
+            time
+        );
+        self.getter.connection.flush();
+
+        loop {
+            if timeout.map(|timeout| start_time.elapsed() >= timeout).unwrap_or(false) {
+                return Err(Error::Timeout);
+            }
+
+            let event = match self.getter.connection.poll_for_event() {
+                Some(event) => event,
+                None => {
+                    thread::park_timeout(Duration::from_millis(POLL_DURATION));
+                    continue;
+                }
+            };
+
+            if event.response_type() & !0x80 == xcb::SELECTION_NOTIFY {
+                let event = unsafe { xcb::cast_event::<xcb::SelectionNotifyEvent>(&event) };
+                if event.selection() != self.getter.atoms.clipboard_manager { continue };
+
+                xcb::delete_property(&self.getter.connection, self.getter.window, property);
+                self.getter.connection.flush();
+
+                return if event.property() == xcb::ATOM_NONE {
+                    Err(Error::Persist)
+                } else {
+                    Ok(())
+                };
+            }
+        }
+    }
 }
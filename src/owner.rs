@@ -0,0 +1,122 @@
+use xcb::{ self, Atom, Window };
+use error::Error;
+use text;
+use { Context, Selection };
+
+
+/// The application owning a selection, as far as the server can tell.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OwnerInfo {
+    /// The window passed to `SetSelectionOwner`.
+    pub window: Window,
+    /// The ancestor of `window` that is a child of the root window.
+    pub toplevel: Window,
+    /// The instance and class names from `WM_CLASS`.
+    pub wm_class: Option<(String, String)>,
+    pub wm_name: Option<String>,
+    /// The process id from `_NET_WM_PID`, as claimed by the client.
+    pub wm_pid: Option<u32>,
+    /// The process id of the client connection, from the X-Resource extension.
+    ///
+    /// Only resolved with the `xres` feature, for clients on the local machine.
+    pub client_pid: Option<u32>
+}
+
+impl Context {
+    /// Identify the owner of a selection, `None` if the selection is not owned.
+    ///
+    /// Owner windows are often unmapped helpers without any property,
+    /// so each property is looked up on the owner first, then on its ancestors.
+    pub fn selection_owner(&self, selection: Selection) -> Result<Option<OwnerInfo>, Error> {
+        let window = xcb::get_selection_owner(&self.connection, selection.atom(&self.atoms))
+            .get_reply()?
+            .owner();
+        if window == xcb::NONE {
+            return Ok(None);
+        }
+
+        let mut ancestors = vec![window];
+        loop {
+            let reply = xcb::query_tree(&self.connection, ancestors[ancestors.len() - 1])
+                .get_reply()?;
+            if reply.parent() == xcb::NONE || reply.parent() == reply.root() {
+                break
+            }
+            ancestors.push(reply.parent());
+        }
+
+        let net_wm_pid = self.get_atom("_NET_WM_PID")?;
+        let find = |property| ancestors.iter()
+            .filter_map(|&window| get_property(self, window, property))
+            .next();
+
+        let wm_class = find(xcb::ATOM_WM_CLASS)
+            .and_then(|(_, value)| {
+                let mut names = value.split(|&b| b == 0)
+                    .map(text::decode_latin1);
+                Some((names.next()?, names.next()?))
+            });
+        let wm_name = find(xcb::ATOM_WM_NAME)
+            .and_then(|(type_, value)| text::decode(&self.atoms, type_, &value).ok());
+        let wm_pid = find(net_wm_pid)
+            .filter(|&(type_, ref value)| type_ == xcb::ATOM_CARDINAL && value.len() >= 4)
+            .map(|(_, value)| u32::from_ne_bytes([value[0], value[1], value[2], value[3]]));
+
+        Ok(Some(OwnerInfo {
+            window,
+            toplevel: ancestors[ancestors.len() - 1],
+            wm_class, wm_name, wm_pid,
+            client_pid: client_pid(self, window)
+        }))
+    }
+}
+
+fn get_property(context: &Context, window: Window, property: Atom) -> Option<(Atom, Vec<u8>)> {
+    let reply = xcb::get_property(
+        &context.connection, false, window,
+        property, xcb::ATOM_ANY, 0, 1024
+    )
+        .get_reply()
+        .ok()?;
+
+    if reply.type_() == xcb::ATOM_NONE {
+        None
+    } else {
+        Some((reply.type_(), reply.value().to_vec()))
+    }
+}
+
+#[cfg(feature = "xres")]
+fn client_pid(context: &Context, window: Window) -> Option<u32> {
+    use xcb::res;
+
+    let present = xcb::query_extension(&context.connection, "X-Resource")
+        .get_reply()
+        .map(|reply| reply.present())
+        .unwrap_or(false);
+    if !present {
+        return None;
+    }
+
+    // Client ids were added in version 1.2.
+    let version = res::query_version(&context.connection, 1, 2)
+        .get_reply()
+        .ok()?;
+    if (version.server_major(), version.server_minor()) < (1, 2) {
+        return None;
+    }
+
+    let spec = res::ClientIdSpec::new(window, res::CLIENT_ID_MASK_LOCAL_CLIENT_PID);
+    let reply = res::query_client_ids(&context.connection, &[spec])
+        .get_reply()
+        .ok()?;
+    let pid = reply.ids()
+        .find(|id| id.spec().mask() & res::CLIENT_ID_MASK_LOCAL_CLIENT_PID != 0)
+        .and_then(|id| id.value().first().cloned());
+    pid
+}
+
+#[cfg(not(feature = "xres"))]
+fn client_pid(_context: &Context, _window: Window) -> Option<u32> {
+    None
+}
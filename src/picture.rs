@@ -0,0 +1,59 @@
+use std::io::Cursor;
+use xcb::Atom;
+use image::{ self, DynamicImage, ImageFormat, RgbaImage };
+use error::Error;
+use provider::Provider;
+use { Context, Target };
+
+
+/// The image targets, in order of preference when loading.
+pub fn targets() -> [(Target, ImageFormat); 3] {
+    [
+        (Target::image_png(), ImageFormat::Png),
+        (Target::image_bmp(), ImageFormat::Bmp),
+        (Target::image_jpeg(), ImageFormat::Jpeg)
+    ]
+}
+
+/// Serves an RGBA image as `image/png`, `image/bmp` and `image/jpeg`.
+///
+/// The image is only encoded when a requestor asks for one of the formats.
+pub struct ImageProvider {
+    formats: Vec<(Atom, ImageFormat)>,
+    image: RgbaImage
+}
+
+impl ImageProvider {
+    pub fn new(context: &Context, image: RgbaImage) -> Result<Self, Error> {
+        let formats = targets().iter()
+            .map(|&(ref target, format)| Ok((target.atom(context)?, format)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(ImageProvider { formats, image })
+    }
+}
+
+impl Provider for ImageProvider {
+    fn targets(&self) -> Vec<Atom> {
+        self.formats.iter().map(|&(atom, _)| atom).collect()
+    }
+
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        let &(_, format) = self.formats.iter().find(|&&(atom, _)| atom == target)?;
+
+        // JPEG has no alpha channel.
+        let image = if format == ImageFormat::Jpeg {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(self.image.clone()).to_rgb8())
+        } else {
+            DynamicImage::ImageRgba8(self.image.clone())
+        };
+
+        let mut value = Cursor::new(Vec::new());
+        image.write_to(&mut value, format).ok()?;
+        Some(value.into_inner())
+    }
+}
+
+/// Decode a value of one of the image formats.
+pub fn decode(format: ImageFormat, value: &[u8]) -> Result<RgbaImage, Error> {
+    Ok(image::load_from_memory_with_format(value, format)?.to_rgba8())
+}
@@ -0,0 +1,109 @@
+use std::io::{ self, Read, Seek, SeekFrom, Cursor };
+use std::sync::{ Arc, Mutex };
+use xcb::Atom;
+
+
+/// Produces the value of a stored selection on demand.
+///
+/// The owner thread calls `convert` each time a requestor asks for one of
+/// the advertised `targets`, so values that nobody pastes are never built.
+pub trait Provider: Send + Sync {
+    /// Targets offered in reply to `TARGETS`.
+    fn targets(&self) -> Vec<Atom>;
+
+    /// Convert the value to `target`, or refuse the conversion with `None`.
+    fn convert(&self, target: Atom) -> Option<Vec<u8>>;
+
+    /// The type of the value converted to `target`.
+    ///
+    /// This is the target itself, except for targets such as `TEXT`
+    /// that let the owner choose the actual encoding.
+    fn type_of(&self, target: Atom) -> Atom {
+        target
+    }
+
+    /// Open the value of `target` for reading, together with its length.
+    ///
+    /// The owner thread reads the value chunk by chunk during incremental transfers,
+    /// so providers backed by large sources can avoid keeping it in memory.
+    /// The default implementation reads from `convert`.
+    fn open(&self, target: Atom) -> Option<(u64, Box<dyn Read + Send>)> {
+        self.convert(target)
+            .map(|value| (value.len() as u64, Box::new(Cursor::new(value)) as Box<dyn Read + Send>))
+    }
+}
+
+impl Provider for Vec<(Atom, Vec<u8>)> {
+    fn targets(&self) -> Vec<Atom> {
+        self.iter().map(|&(target, _)| target).collect()
+    }
+
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        self.iter()
+            .find(|&&(t, _)| t == target)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+/// Serves a single target from a seekable source, such as a file.
+///
+/// Each transfer keeps its own position in the source,
+/// so several requestors can be served at the same time.
+pub struct ReaderProvider<R> {
+    target: Atom,
+    source: Arc<Mutex<R>>
+}
+
+impl<R: Read + Seek + Send + 'static> ReaderProvider<R> {
+    pub fn new(target: Atom, source: R) -> Self {
+        ReaderProvider { target, source: Arc::new(Mutex::new(source)) }
+    }
+
+    fn reader(&self) -> Option<(u64, SharedReader<R>)> {
+        let len = self.source
+            .lock()
+            .ok()?
+            .seek(SeekFrom::End(0))
+            .ok()?;
+        Some((len, SharedReader { source: Arc::clone(&self.source), pos: 0 }))
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> Provider for ReaderProvider<R> {
+    fn targets(&self) -> Vec<Atom> {
+        vec![self.target]
+    }
+
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        let (len, mut reader) = self.open(target)?;
+        let mut value = Vec::with_capacity(len as usize);
+        reader.read_to_end(&mut value).ok()?;
+        Some(value)
+    }
+
+    fn open(&self, target: Atom) -> Option<(u64, Box<dyn Read + Send>)> {
+        if target != self.target {
+            return None;
+        }
+
+        let (len, reader) = self.reader()?;
+        Some((len, Box::new(reader)))
+    }
+}
+
+struct SharedReader<R> {
+    source: Arc<Mutex<R>>,
+    pos: u64
+}
+
+impl<R: Read + Seek> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut source = self.source
+            .lock()
+            .map_err(|_| io::Error::other("source lock is poisoned"))?;
+        source.seek(SeekFrom::Start(self.pos))?;
+        let len = source.read(buf)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
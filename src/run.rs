@@ -1,9 +1,13 @@
 use std::cmp;
+use std::io::Read;
+use std::time::{ Duration, Instant };
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc::Receiver;
 use std::collections::HashMap;
-use xcb::{ self, Atom };
-use ::{ INCR_CHUNK_SIZE, Context, SetMap };
+use xcb::{ self, Atom, Window, Timestamp };
+use ::{ INCR_CHUNK_SIZE, Context, SetMap, LostSenders, IncrTimeout, SelectionLost, Selection, wait_readable };
+use provider::Provider;
 
 macro_rules! try_continue {
     ( $expr:expr ) => {
@@ -16,107 +20,285 @@ macro_rules! try_continue {
 
 struct IncrState {
     selection: Atom,
-    requestor: Atom,
+    type_: Atom,
+    requestor: Window,
     property: Atom,
-    pos: usize
+    reader: Box<dyn Read + Send>,
+    deadline: Instant
 }
 
-pub fn run(context: &Arc<Context>, setmap: &SetMap, max_length: usize, receiver: &Receiver<Atom>) {
-    let mut incr_map = HashMap::new();
-    let mut state_map = HashMap::new();
+enum Conversion {
+    Refused,
+    Done,
+    Incr(Atom, Box<dyn Read + Send>)
+}
 
-    while let Some(event) = context.connection.wait_for_event() {
-        while let Ok(selection) = receiver.try_recv() {
-            if let Some(property) = incr_map.remove(&selection) {
-                state_map.remove(&property);
+fn convert(context: &Context, owned: &(Timestamp, Box<dyn Provider>), max_length: usize, requestor: Window, target: Atom, property: Atom)
+    -> Conversion
+{
+    let &(time, ref provider) = owned;
+    let provided = provider.targets();
+
+    if target == context.atoms.targets {
+        let mut targets = Vec::with_capacity(provided.len() + 3);
+        targets.push(context.atoms.targets);
+        targets.push(context.atoms.multiple);
+        targets.push(context.atoms.timestamp);
+        targets.extend(provided);
+
+        xcb::change_property(
+            &context.connection, xcb::PROP_MODE_REPLACE as u8,
+            requestor, property, xcb::ATOM_ATOM, 32,
+            &targets
+        );
+        return Conversion::Done;
+    } else if target == context.atoms.timestamp {
+        xcb::change_property(
+            &context.connection, xcb::PROP_MODE_REPLACE as u8,
+            requestor, property, xcb::ATOM_INTEGER, 32,
+            &[time]
+        );
+        return Conversion::Done;
+    }
+
+    // Only the advertised targets are converted, anything else is refused.
+    if !provided.contains(&target) {
+        return Conversion::Refused;
+    }
+    let (len, mut reader) = match provider.open(target) {
+        Some(value) => value,
+        None => return Conversion::Refused
+    };
+    let type_ = provider.type_of(target);
+
+    if len < (max_length - 24) as u64 {
+        let mut value = Vec::with_capacity(len as usize);
+        if reader.read_to_end(&mut value).is_err() {
+            return Conversion::Refused;
+        }
+
+        xcb::change_property(
+            &context.connection, xcb::PROP_MODE_REPLACE as u8,
+            requestor, property, type_, 8,
+            &value
+        );
+        Conversion::Done
+    } else {
+        xcb::change_window_attributes(
+            &context.connection, requestor,
+            &[(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE | xcb::EVENT_MASK_STRUCTURE_NOTIFY)]
+        );
+        xcb::change_property(
+            &context.connection, xcb::PROP_MODE_REPLACE as u8,
+            requestor, property, context.atoms.incr, 32,
+            &[cmp::min(len, u64::from(u32::MAX)) as u32]
+        );
+        Conversion::Incr(type_, reader)
+    }
+}
+
+/// Tell the requestor the conversion is done, `property` is None if it was refused.
+fn notify(context: &Context, event: &xcb::SelectionRequestEvent, property: Atom) {
+    xcb::send_event(
+        &context.connection, false, event.requestor(), 0,
+        &xcb::SelectionNotifyEvent::new(
+            event.time(),
+            event.requestor(),
+            event.selection(),
+            event.target(),
+            property
+        )
+    );
+    context.connection.flush();
+}
+
+/// Stop listening to a requestor once none of its transfers are left.
+fn release(context: &Context, state_map: &HashMap<(Window, Atom), IncrState>, requestor: Window) {
+    if !state_map.keys().any(|&(window, _)| window == requestor) {
+        xcb::change_window_attributes(
+            &context.connection, requestor,
+            &[(xcb::CW_EVENT_MASK, 0)]
+        );
+    }
+}
+
+/// Drop the transfers whose requestor stopped deleting the property.
+fn expire(context: &Context, state_map: &mut HashMap<(Window, Atom), IncrState>) {
+    let now = Instant::now();
+    let expired = state_map.iter()
+        .filter(|&(_, state)| state.deadline <= now)
+        .map(|(&key, _)| key)
+        .collect::<Vec<_>>();
+
+    if expired.is_empty() {
+        return;
+    }
+
+    for key in expired {
+        state_map.remove(&key);
+        release(context, state_map, key.0);
+    }
+    context.connection.flush();
+}
+
+pub fn run(context: &Arc<Context>, setmap: &SetMap, lost: &LostSenders, incr_timeout: &IncrTimeout, max_length: usize, receiver: &Receiver<Atom>) {
+    // INCR transfers in progress, keyed by requestor window and property,
+    // so that several requestors can be served at the same time.
+    let mut state_map: HashMap<(Window, Atom), IncrState> = HashMap::new();
+
+    loop {
+        expire(context, &mut state_map);
+
+        let event = match context.connection.poll_for_event() {
+            Some(event) => event,
+            None => {
+                if context.connection.has_error().is_err() { break };
+
+                // Block until the next event, or until the earliest pending
+                // transfer is due to be dropped.
+                let timeout = state_map.values()
+                    .map(|state| state.deadline)
+                    .min()
+                    .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                if wait_readable(&context.connection, timeout).is_err() { break };
+                continue
             }
+        };
+
+        while let Ok(selection) = receiver.try_recv() {
+            state_map.retain(|_, state| state.selection != selection);
         }
 
+        let timeout = Duration::from_millis(incr_timeout.load(Ordering::Relaxed));
+
         match event.response_type() & !0x80 {
             xcb::SELECTION_REQUEST => {
                 let event = unsafe { xcb::cast_event::<xcb::SelectionRequestEvent>(&event) };
                 let read_map = try_continue!(setmap.read().ok());
-                let &(target, ref value) = try_continue!(read_map.get(&event.selection()));
+                let owned = match read_map.get(&event.selection()) {
+                    Some(owned) => owned,
+                    None => {
+                        // The selection was lost or never stored.
+                        notify(context, event, xcb::ATOM_NONE);
+                        continue
+                    }
+                };
 
-                if event.target() == context.atoms.targets {
-                    xcb::change_property(
-                        &context.connection, xcb::PROP_MODE_REPLACE as u8,
-                        event.requestor(), event.property(), xcb::ATOM_ATOM, 32,
-                        &[context.atoms.targets, target]
-                    );
-                } else if value.len() < max_length - 24 {
-                    xcb::change_property(
-                        &context.connection, xcb::PROP_MODE_REPLACE as u8,
-                        event.requestor(), event.property(), target, 8,
-                        value
-                    );
+                let mut conversions = Vec::new();
+                let mut property = event.property();
+
+                if event.target() == context.atoms.multiple {
+                    // The property holds a list of (target, property) pairs,
+                    // each pair that can't be converted is replaced by None.
+                    let pairs = xcb::get_property(
+                        &context.connection, false, event.requestor(),
+                        event.property(), xcb::ATOM_ANY, 0, u32::MAX
+                    )
+                        .get_reply()
+                        .ok()
+                        .filter(|reply| reply.format() == 32
+                            && (reply.type_() == context.atoms.atom_pair || reply.type_() == xcb::ATOM_ATOM))
+                        .map(|reply| reply.value::<Atom>().to_vec());
+
+                    match pairs {
+                        Some(mut pairs) => {
+                            for pair in pairs.chunks_exact_mut(2) {
+                                match convert(context, owned, max_length, event.requestor(), pair[0], pair[1]) {
+                                    Conversion::Refused => pair[1] = xcb::ATOM_NONE,
+                                    Conversion::Done => (),
+                                    Conversion::Incr(type_, reader) => conversions.push((type_, pair[1], reader))
+                                }
+                            }
+
+                            xcb::change_property(
+                                &context.connection, xcb::PROP_MODE_REPLACE as u8,
+                                event.requestor(), event.property(), context.atoms.atom_pair, 32,
+                                &pairs
+                            );
+                        },
+                        None => property = xcb::ATOM_NONE
+                    }
                 } else {
-                    xcb::change_window_attributes(
-                        &context.connection, event.requestor(),
-                        &[(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)]
-                    );
-                    xcb::change_property(
-                        &context.connection, xcb::PROP_MODE_REPLACE as u8,
-                        event.requestor(), event.property(), context.atoms.incr, 32,
-                        &[0u8; 0]
-                    );
+                    match convert(context, owned, max_length, event.requestor(), event.target(), event.property()) {
+                        Conversion::Refused => property = xcb::ATOM_NONE,
+                        Conversion::Done => (),
+                        Conversion::Incr(type_, reader) => conversions.push((type_, event.property(), reader))
+                    }
+                }
 
-                    incr_map.insert(event.selection(), event.property());
+                for (type_, property, reader) in conversions {
                     state_map.insert(
-                        event.property(),
+                        (event.requestor(), property),
                         IncrState {
                             selection: event.selection(),
+                            type_,
                             requestor: event.requestor(),
-                            property: event.property(),
-                            pos: 0
+                            property,
+                            reader,
+                            deadline: Instant::now() + timeout
                         }
                     );
                 }
 
-                xcb::send_event(
-                    &context.connection, false, event.requestor(), 0,
-                    &xcb::SelectionNotifyEvent::new(
-                        event.time(),
-                        event.requestor(),
-                        event.selection(),
-                        event.target(),
-                        event.property()
-                    )
-                );
-                context.connection.flush();
+                notify(context, event, property);
             },
             xcb::PROPERTY_NOTIFY => {
                 let event = unsafe { xcb::cast_event::<xcb::PropertyNotifyEvent>(&event) };
                 if event.state() != xcb::PROPERTY_DELETE as u8 { continue };
 
                 let is_end = {
-                    let state = try_continue!(state_map.get_mut(&event.atom()));
-                    let read_setmap = try_continue!(setmap.read().ok());
-                    let &(target, ref value) = try_continue!(read_setmap.get(&state.selection));
+                    let state = try_continue!(state_map.get_mut(&(event.window(), event.atom())));
+
+                    // A read error ends the transfer early, as there is no way to report it.
+                    let mut chunk = Vec::with_capacity(INCR_CHUNK_SIZE);
+                    if (&mut state.reader).take(INCR_CHUNK_SIZE as u64).read_to_end(&mut chunk).is_err() {
+                        chunk.clear();
+                    }
 
-                    let len = cmp::min(INCR_CHUNK_SIZE, value.len() - state.pos);
                     xcb::change_property(
                         &context.connection, xcb::PROP_MODE_REPLACE as u8,
-                        state.requestor, state.property, target, 8,
-                        &value[state.pos..][..len]
+                        state.requestor, state.property, state.type_, 8,
+                        &chunk
                     );
 
-                    state.pos += len;
-                    len == 0
+                    state.deadline = Instant::now() + timeout;
+                    chunk.is_empty()
                 };
 
                 if is_end {
-                    state_map.remove(&event.atom());
+                    state_map.remove(&(event.window(), event.atom()));
+                    release(context, &state_map, event.window());
                 }
                 context.connection.flush();
             },
+            xcb::DESTROY_NOTIFY => {
+                let event = unsafe { xcb::cast_event::<xcb::DestroyNotifyEvent>(&event) };
+                state_map.retain(|&(window, _), _| window != event.window());
+            },
             xcb::SELECTION_CLEAR => {
                 let event = unsafe { xcb::cast_event::<xcb::SelectionClearEvent>(&event) };
-                if let Some(property) = incr_map.remove(&event.selection()) {
-                    state_map.remove(&property);
-                }
-                if let Ok(mut write_setmap) = setmap.write() {
-                    write_setmap.remove(&event.selection());
+                // A clear older than the current ownership was overtaken by a new `store`.
+                let is_stale = {
+                    let mut write_setmap = try_continue!(setmap.write().ok());
+                    let is_stale = write_setmap.get(&event.selection())
+                        .map(|&(time, _)| time > event.time())
+                        .unwrap_or(true);
+                    if !is_stale {
+                        write_setmap.remove(&event.selection());
+                    }
+                    is_stale
+                };
+
+                if !is_stale {
+                    state_map.retain(|_, state| state.selection != event.selection());
+
+                    if let Ok(mut senders) = lost.lock() {
+                        let lost = SelectionLost {
+                            selection: Selection::from_atom(&context.atoms, event.selection()),
+                            time: event.time()
+                        };
+                        senders.retain(|sender| sender.send(lost).is_ok());
+                    }
                 }
             },
             _ => ()
@@ -0,0 +1,113 @@
+use std::borrow::Cow;
+use xcb::Atom;
+use error::Error;
+use { Atoms, Context };
+
+
+/// A selection, such as the clipboard.
+///
+/// Selections compare by variant, so build them from atoms with `Selection::from_atom`,
+/// as the events of this crate do, for them to match the selections of events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Selection {
+    Primary,
+    Secondary,
+    Clipboard,
+    /// Any other selection, by atom.
+    ///
+    /// Must not hold the atom of `PRIMARY`, `SECONDARY` or `CLIPBOARD`,
+    /// which `Selection::from_atom` maps to their own variants.
+    Custom(Atom)
+}
+
+impl Selection {
+    pub fn atom(self, atoms: &Atoms) -> Atom {
+        match self {
+            Selection::Primary => atoms.primary,
+            Selection::Secondary => atoms.secondary,
+            Selection::Clipboard => atoms.clipboard,
+            Selection::Custom(atom) => atom
+        }
+    }
+
+    /// The selection named by `atom`, using the well-known variants where possible.
+    pub fn from_atom(atoms: &Atoms, atom: Atom) -> Selection {
+        if atom == atoms.primary {
+            Selection::Primary
+        } else if atom == atoms.secondary {
+            Selection::Secondary
+        } else if atom == atoms.clipboard {
+            Selection::Clipboard
+        } else {
+            Selection::Custom(atom)
+        }
+    }
+}
+
+/// A format a selection can be converted to.
+///
+/// Besides the ICCCM targets, owners commonly offer MIME types,
+/// which are interned by name when the target is used.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Target {
+    Targets,
+    Timestamp,
+    String,
+    Utf8String,
+    Text,
+    CompoundText,
+    /// A target interned by name, usually a MIME type.
+    Name(Cow<'static, str>),
+    /// Any other target, by atom.
+    Custom(Atom)
+}
+
+impl Target {
+    /// A target named by a MIME type, such as `image/png`.
+    pub fn mime<S: Into<Cow<'static, str>>>(name: S) -> Target {
+        Target::Name(name.into())
+    }
+
+    pub fn text_plain() -> Target {
+        Target::mime("text/plain")
+    }
+
+    pub fn text_plain_utf8() -> Target {
+        Target::mime("text/plain;charset=utf-8")
+    }
+
+    pub fn text_html() -> Target {
+        Target::mime("text/html")
+    }
+
+    pub fn text_uri_list() -> Target {
+        Target::mime("text/uri-list")
+    }
+
+    pub fn image_png() -> Target {
+        Target::mime("image/png")
+    }
+
+    pub fn image_bmp() -> Target {
+        Target::mime("image/bmp")
+    }
+
+    pub fn image_jpeg() -> Target {
+        Target::mime("image/jpeg")
+    }
+
+    /// Resolve the target to an atom, interning its name if needed.
+    pub fn atom(&self, context: &Context) -> Result<Atom, Error> {
+        let atoms = &context.atoms;
+        Ok(match *self {
+            Target::Targets => atoms.targets,
+            Target::Timestamp => atoms.timestamp,
+            Target::String => atoms.string,
+            Target::Utf8String => atoms.utf8_string,
+            Target::Text => atoms.text,
+            Target::CompoundText => atoms.compound_text,
+            Target::Name(ref name) => context.get_atom(name)?,
+            Target::Custom(atom) => atom
+        })
+    }
+}
@@ -0,0 +1,205 @@
+//! Conversion between strings and the text targets of ICCCM.
+//!
+//! `STRING` is Latin-1, and `COMPOUND_TEXT` is encoded with Latin-1 as its
+//! initial state and UTF-8 segments for everything else.
+
+use xcb::Atom;
+use error::Error;
+use provider::Provider;
+use Atoms;
+
+const ESC: u8 = 0x1b;
+const CSI: u8 = 0x9b;
+
+/// Serves a string as `UTF8_STRING`, `COMPOUND_TEXT`, `STRING` and `TEXT`.
+pub struct TextProvider {
+    atoms: Atoms,
+    text: String
+}
+
+impl TextProvider {
+    pub fn new<S: Into<String>>(atoms: &Atoms, text: S) -> Self {
+        TextProvider { atoms: atoms.clone(), text: text.into() }
+    }
+}
+
+impl Provider for TextProvider {
+    fn targets(&self) -> Vec<Atom> {
+        vec![
+            self.atoms.utf8_string,
+            self.atoms.compound_text,
+            self.atoms.string,
+            self.atoms.text
+        ]
+    }
+
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        let target = self.type_of(target);
+
+        if target == self.atoms.utf8_string {
+            Some(self.text.as_bytes().to_vec())
+        } else if target == self.atoms.compound_text {
+            Some(encode_compound_text(&self.text))
+        } else if target == self.atoms.string {
+            Some(encode_latin1(&self.text))
+        } else {
+            None
+        }
+    }
+
+    /// `TEXT` is answered with `STRING` when possible, and `COMPOUND_TEXT` otherwise.
+    fn type_of(&self, target: Atom) -> Atom {
+        if target != self.atoms.text {
+            target
+        } else if self.text.chars().all(is_latin1) {
+            self.atoms.string
+        } else {
+            self.atoms.compound_text
+        }
+    }
+}
+
+/// Decode a value of one of the text types.
+///
+/// Trailing NULs, which some owners include, are dropped.
+/// `UTF8_STRING` values must be valid UTF-8.
+pub fn decode(atoms: &Atoms, type_: Atom, value: &[u8]) -> Result<String, Error> {
+    let len = value.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    let value = &value[..len];
+
+    if type_ == atoms.utf8_string {
+        Ok(String::from_utf8(value.to_vec())?)
+    } else if type_ == atoms.compound_text {
+        Ok(decode_compound_text(value))
+    } else if type_ == atoms.string {
+        Ok(decode_latin1(value))
+    } else {
+        Err(Error::UnexpectedType(type_))
+    }
+}
+
+fn is_latin1(c: char) -> bool {
+    matches!(c, '\t' | '\n' | ' '..='~' | '\u{a0}'..='\u{ff}')
+}
+
+/// Encode as Latin-1, replacing the characters it lacks by `?`.
+pub fn encode_latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if is_latin1(c) { c as u8 } else { b'?' })
+        .collect()
+}
+
+pub fn decode_latin1(value: &[u8]) -> String {
+    value.iter().map(|&b| b as char).collect()
+}
+
+pub fn encode_compound_text(text: &str) -> Vec<u8> {
+    let mut output = Vec::with_capacity(text.len());
+    let mut in_utf8 = false;
+    let mut buf = [0; 4];
+
+    for c in text.chars() {
+        if is_latin1(c) {
+            if in_utf8 {
+                output.extend_from_slice(&[ESC, b'%', b'@']);
+                in_utf8 = false;
+            }
+            output.push(c as u8);
+        } else if !c.is_control() {
+            if !in_utf8 {
+                output.extend_from_slice(&[ESC, b'%', b'G']);
+                in_utf8 = true;
+            }
+            output.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+
+    if in_utf8 {
+        output.extend_from_slice(&[ESC, b'%', b'@']);
+    }
+    output
+}
+
+/// Decode the Latin-1, ASCII and UTF-8 parts of compound text,
+/// characters of other character sets are replaced by U+FFFD.
+pub fn decode_compound_text(value: &[u8]) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut gl_ascii = true;
+    let mut gr_latin1 = true;
+    let mut i = 0;
+
+    while i < value.len() {
+        match value[i] {
+            ESC => {
+                // ESC, intermediate bytes, final byte.
+                let start = i + 1;
+                let mut end = start;
+                while end < value.len() && (0x20..0x30).contains(&value[end]) {
+                    end += 1;
+                }
+                if end >= value.len() {
+                    break;
+                }
+
+                match (&value[start..end], value[end]) {
+                    (b"%", b'G') => {
+                        let rest = &value[end + 1..];
+                        let len = rest.windows(3)
+                            .position(|w| w == [ESC, b'%', b'@'])
+                            .unwrap_or(rest.len());
+                        output.push_str(&String::from_utf8_lossy(&rest[..len]));
+                        i = end + 1 + len + 3;
+                        continue;
+                    },
+                    (b"%/", _) if end + 2 < value.len() => {
+                        // Extended segment: two length bytes, then the
+                        // encoding name terminated by STX, then the text.
+                        let len = (value[end + 1] as usize & 0x7f) * 128 + (value[end + 2] as usize & 0x7f);
+                        let segment = &value[end + 3..];
+                        let segment = &segment[..len.min(segment.len())];
+                        let name_len = segment.iter().position(|&b| b == 0x02).unwrap_or(segment.len());
+                        let name = &segment[..name_len];
+                        // Xlib labels UTF-8 segments as ISO 10646-1.
+                        let is_utf8 = name.eq_ignore_ascii_case(b"utf-8") || name.eq_ignore_ascii_case(b"iso10646-1");
+                        if is_utf8 && name_len < segment.len() {
+                            output.push_str(&String::from_utf8_lossy(&segment[name_len + 1..]));
+                        } else if !segment.is_empty() {
+                            output.push('\u{fffd}');
+                        }
+                        i = end + 3 + segment.len();
+                        continue;
+                    },
+                    (b"(", final_) => gl_ascii = final_ == b'B' || final_ == b'J',
+                    (b"-", final_) => gr_latin1 = final_ == b'A',
+                    (b")", _) | (b"$(", _) | (b"$)", _) => gl_ascii = false,
+                    (b"$-", _) => gr_latin1 = false,
+                    _ => ()
+                }
+                i = end + 1;
+            },
+            CSI => {
+                // Directionality control, skipped up to its final byte.
+                i += 1;
+                while i < value.len() && !(0x40..0x7f).contains(&value[i]) {
+                    i += 1;
+                }
+                i += 1;
+            },
+            b @ b'\t' | b @ b'\n' => {
+                output.push(b as char);
+                i += 1;
+            },
+            b @ 0x20..=0x7e => {
+                output.push(if gl_ascii { b as char } else { '\u{fffd}' });
+                i += 1;
+            },
+            b @ 0xa0..=0xff => {
+                output.push(if gr_latin1 { b as char } else { '\u{fffd}' });
+                i += 1;
+            },
+            _ => i += 1
+        }
+    }
+
+    output
+}
@@ -0,0 +1,119 @@
+use xcb::{ self, Window, Timestamp };
+use xcb::base::ConnError;
+use error::Error;
+use { Context, Selection };
+
+
+/// Why the owner of a selection changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// A client set a new owner.
+    SetOwner,
+    /// The owner window was destroyed.
+    WindowDestroy,
+    /// The client owning the selection closed its connection.
+    ClientClose
+}
+
+/// A change of selection owner, as reported by XFixes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelectionEvent {
+    pub selection: Selection,
+    /// The new owner, `xcb::NONE` if the selection is no longer owned.
+    pub owner: Window,
+    pub timestamp: Timestamp,
+    pub selection_timestamp: Timestamp,
+    pub kind: ChangeKind
+}
+
+const SELECTION_EVENT_MASK: u32 =
+    xcb::xfixes::SELECTION_EVENT_MASK_SET_SELECTION_OWNER |
+    xcb::xfixes::SELECTION_EVENT_MASK_SELECTION_CLIENT_CLOSE |
+    xcb::xfixes::SELECTION_EVENT_MASK_SELECTION_WINDOW_DESTROY;
+
+/// Watch a set of selections for owner changes.
+///
+/// Unlike `Clipboard::load_wait`, the watcher selects XFixes input once,
+/// and yields every owner change without converting the selection.
+/// Events of all watched selections are tagged with the selection they belong to.
+pub struct SelectionWatcher {
+    pub context: Context,
+    root: Window,
+    event_base: u8
+}
+
+impl SelectionWatcher {
+    /// Create a watcher for `selections` on its own connection.
+    pub fn new<I>(selections: I) -> Result<Self, Error>
+        where I: IntoIterator<Item = Selection>
+    {
+        let context = Context::new(None)?;
+
+        let xfixes = xcb::query_extension(&context.connection, "XFIXES").get_reply()?;
+        if !xfixes.present() {
+            return Err(Error::MissingExtension("XFIXES"));
+        }
+        xcb::xfixes::query_version(&context.connection, 5, 0).get_reply()?;
+
+        let root = context.connection.get_setup().roots()
+            .nth(context.screen as usize)
+            .ok_or(Error::XcbConn(ConnError::ClosedInvalidScreen))?
+            .root();
+        let watcher = SelectionWatcher { context, root, event_base: xfixes.first_event() };
+        for selection in selections {
+            watcher.watch(selection);
+        }
+        Ok(watcher)
+    }
+
+    /// Start watching `selection` as well.
+    pub fn watch(&self, selection: Selection) {
+        xcb::xfixes::select_selection_input(
+            &self.context.connection, self.root, selection.atom(&self.context.atoms), SELECTION_EVENT_MASK);
+        self.context.connection.flush();
+    }
+
+    /// Stop watching `selection`.
+    pub fn unwatch(&self, selection: Selection) {
+        xcb::xfixes::select_selection_input(
+            &self.context.connection, self.root, selection.atom(&self.context.atoms), 0);
+        self.context.connection.flush();
+    }
+
+    /// Block until the owner of the selection changes.
+    pub fn wait(&self) -> Result<SelectionEvent, Error> {
+        loop {
+            let event = self.context.connection.wait_for_event()
+                .ok_or(Error::IoError)?;
+
+            if event.response_type() & !0x80 != self.event_base + xcb::xfixes::SELECTION_NOTIFY {
+                continue
+            }
+
+            let event = unsafe { xcb::cast_event::<xcb::xfixes::SelectionNotifyEvent>(&event) };
+            let kind = match event.subtype() as u32 {
+                xcb::xfixes::SELECTION_EVENT_SET_SELECTION_OWNER => ChangeKind::SetOwner,
+                xcb::xfixes::SELECTION_EVENT_SELECTION_WINDOW_DESTROY => ChangeKind::WindowDestroy,
+                xcb::xfixes::SELECTION_EVENT_SELECTION_CLIENT_CLOSE => ChangeKind::ClientClose,
+                _ => continue
+            };
+
+            return Ok(SelectionEvent {
+                selection: Selection::from_atom(&self.context.atoms, event.selection()),
+                owner: event.owner(),
+                timestamp: event.timestamp(),
+                selection_timestamp: event.selection_timestamp(),
+                kind
+            });
+        }
+    }
+}
+
+impl Iterator for SelectionWatcher {
+    type Item = SelectionEvent;
+
+    /// Block until the next owner change, `None` once the connection is closed.
+    fn next(&mut self) -> Option<SelectionEvent> {
+        self.wait().ok()
+    }
+}
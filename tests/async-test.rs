@@ -0,0 +1,46 @@
+#![cfg(feature = "async")]
+
+extern crate async_io;
+extern crate futures_lite;
+extern crate x11_clipboard;
+
+use std::time::{ Instant, Duration };
+use futures_lite::future;
+use x11_clipboard::{ Clipboard, Selection, Target };
+
+
+#[test]
+fn it_work_async() {
+    let data = format!("{:?}", Instant::now());
+    let clipboard = Clipboard::new().unwrap();
+
+    let atom_property = clipboard.setter.atoms.property;
+
+    clipboard.store(Selection::Clipboard, Target::Utf8String, data.as_bytes()).unwrap();
+
+    let output = async_io::block_on(
+        clipboard.load_async(Selection::Clipboard, Target::Utf8String, atom_property, Duration::from_secs(3))
+    ).unwrap();
+    assert_eq!(output, data.as_bytes());
+}
+
+#[test]
+fn it_work_async_concurrent() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let first = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_ASYNC_FIRST").unwrap());
+    let second = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_ASYNC_SECOND").unwrap());
+    let atom_property = clipboard.getter.atoms.property;
+    let dur = Duration::from_secs(3);
+
+    clipboard.store(first, Target::Utf8String, "first").unwrap();
+    clipboard.store(second, Target::Utf8String, "second").unwrap();
+
+    // Both futures are polled in turn, the second waits until the first is done.
+    let (output_first, output_second) = async_io::block_on(future::zip(
+        clipboard.load_async(first, Target::Utf8String, atom_property, dur),
+        clipboard.load_async(second, Target::Utf8String, atom_property, dur)
+    ));
+    assert_eq!(output_first.unwrap(), b"first");
+    assert_eq!(output_second.unwrap(), b"second");
+}
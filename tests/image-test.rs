@@ -0,0 +1,28 @@
+#![cfg(feature = "image")]
+
+extern crate x11_clipboard;
+
+use std::io::Cursor;
+use std::time::Duration;
+use x11_clipboard::{ Clipboard, Selection, Target };
+use x11_clipboard::image::{ self, ImageFormat, Rgba, RgbaImage };
+
+
+#[test]
+fn it_work_image() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_IMAGE").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+    let dur = Duration::from_secs(3);
+
+    let data = RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8 * 16, y as u8 * 32, 0, 255 - x as u8]));
+    clipboard.store_image(selection, data.clone()).unwrap();
+
+    let output = clipboard.load_image(selection, dur).unwrap();
+    assert!(output == data);
+
+    let output = clipboard.load(selection, Target::image_jpeg(), atom_property, dur).unwrap();
+    let output = image::load(Cursor::new(output), ImageFormat::Jpeg).unwrap();
+    assert_eq!((output.width(), output.height()), (16, 8));
+}
@@ -1,33 +1,722 @@
 extern crate x11_clipboard;
 
+use std::thread;
+use std::io::Cursor;
+use std::path::PathBuf;
 use std::time::{ Instant, Duration };
-use x11_clipboard::Clipboard;
+use std::sync::{ Arc, Mutex, MutexGuard, PoisonError };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use x11_clipboard::{ Clipboard, Context, Selection, Target, SelectionWatcher, ChangeKind };
+use x11_clipboard::error::Error;
+use x11_clipboard::files::{ self, FileList, FileOperation };
+use x11_clipboard::html::Content;
+use x11_clipboard::xcb::{ self, Atom };
+use x11_clipboard::provider::Provider;
+use x11_clipboard::text;
 
 
+/// Serializes the tests that use CLIPBOARD, which the text helpers and `persist` are tied to.
+static CLIPBOARD_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_clipboard() -> MutexGuard<'static, ()> {
+    CLIPBOARD_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A value one byte too large for a single request, so that it is sent incrementally.
+fn incr_data(clipboard: &Clipboard) -> Vec<u8> {
+    let size = clipboard.setter.connection.get_maximum_request_length() as usize * 4 + 1;
+    (0..size).map(|i| i as u8).collect()
+}
+
 #[test]
 fn it_work() {
+    let _lock = lock_clipboard();
     let data = format!("{:?}", Instant::now());
     let clipboard = Clipboard::new().unwrap();
 
-    let atom_clipboard = clipboard.setter.atoms.clipboard;
-    let atom_utf8string = clipboard.setter.atoms.utf8_string;
+    let selection = Selection::Clipboard;
     let atom_property = clipboard.setter.atoms.property;
 
-    clipboard.store(atom_clipboard, atom_utf8string, data.as_bytes()).unwrap();
+    clipboard.store(selection, Target::Utf8String, data.as_bytes()).unwrap();
 
-    let output = clipboard.load(atom_clipboard, atom_utf8string, atom_property, None).unwrap();
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, None).unwrap();
     assert_eq!(output, data.as_bytes());
 
     let data = format!("{:?}", Instant::now());
-    clipboard.store(atom_clipboard, atom_utf8string, data.as_bytes()).unwrap();
+    clipboard.store(selection, Target::Utf8String, data.as_bytes()).unwrap();
 
-    let output = clipboard.load(atom_clipboard, atom_utf8string, atom_property, None).unwrap();
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, None).unwrap();
     assert_eq!(output, data.as_bytes());
 
-    let output = clipboard.load(atom_clipboard, atom_utf8string, atom_property, None).unwrap();
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, None).unwrap();
     assert_eq!(output, data.as_bytes());
 
     let dur = Duration::from_secs(3);
-    let output = clipboard.load(atom_clipboard, atom_utf8string, atom_property, dur).unwrap();
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, dur).unwrap();
     assert_eq!(output, data.as_bytes());
 }
+
+#[test]
+fn it_work_multiple_targets() {
+    let data = format!("{:?}", Instant::now());
+    let html = format!("<b>{}</b>", data);
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_MULTIPLE_TARGETS").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+
+    clipboard.store_targets(selection, vec![
+        (Target::Utf8String, data.as_bytes()),
+        (Target::text_html(), html.as_bytes())
+    ]).unwrap();
+
+    let output = clipboard.load(selection, Target::text_html(), atom_property, None).unwrap();
+    assert_eq!(output, html.as_bytes());
+
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, None).unwrap();
+    assert_eq!(output, data.as_bytes());
+}
+
+#[test]
+fn it_work_available_targets() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_AVAILABLE_TARGETS").unwrap());
+    let atom_html = clipboard.setter.get_atom("text/html").unwrap();
+
+    clipboard.store_targets(selection, vec![
+        (Target::Utf8String, "text"),
+        (Target::text_html(), "<p>text</p>")
+    ]).unwrap();
+
+    let targets = clipboard.available_targets(selection, Duration::from_secs(3)).unwrap();
+    assert!(targets.contains(&(clipboard.setter.atoms.utf8_string, "UTF8_STRING".to_owned())));
+    assert!(targets.contains(&(atom_html, "text/html".to_owned())));
+}
+
+#[test]
+fn it_work_timestamp() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_TIMESTAMP").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+
+    clipboard.store(selection, Target::Utf8String, "text").unwrap();
+
+    let time = clipboard.ownership_timestamp(selection).unwrap().unwrap();
+    let output = clipboard.load(selection, Target::Timestamp, atom_property, Duration::from_secs(3)).unwrap();
+    assert_eq!(output, time.to_ne_bytes());
+}
+
+#[test]
+fn it_work_persist() {
+    let _lock = lock_clipboard();
+    let clipboard = Clipboard::new().unwrap();
+    let dur = Duration::from_secs(3);
+
+    // Nothing to hand over for a clipboard that never stored CLIPBOARD.
+    clipboard.persist(dur).unwrap();
+
+    clipboard.store_targets(Selection::Clipboard, vec![
+        (Target::Utf8String, "persist"),
+        (Target::text_html(), "<b>persist</b>")
+    ]).unwrap();
+    let targets = vec![clipboard.getter.atoms.utf8_string, Target::text_html().atom(&clipboard.getter).unwrap()];
+
+    let manager = Context::new(None).unwrap();
+    let atom_clipboard_manager = manager.atoms.clipboard_manager;
+    let owner = xcb::get_selection_owner(&manager.connection, atom_clipboard_manager)
+        .get_reply()
+        .unwrap()
+        .owner();
+    if owner == xcb::NONE {
+        match clipboard.persist(dur) {
+            Err(Error::NoManager) => (),
+            other => panic!("expected no clipboard manager, got {:?}", other)
+        }
+    }
+
+    // Act as the clipboard manager, answering SAVE_TARGETS with the list of targets it was given.
+    xcb::set_selection_owner(&manager.connection, manager.window, atom_clipboard_manager, xcb::CURRENT_TIME);
+    let owner = xcb::get_selection_owner(&manager.connection, atom_clipboard_manager)
+        .get_reply()
+        .unwrap()
+        .owner();
+    assert_eq!(owner, manager.window);
+
+    let handle = thread::spawn(move || loop {
+        let event = manager.connection.wait_for_event().unwrap();
+        if event.response_type() & !0x80 != xcb::SELECTION_REQUEST { continue }
+        let event = unsafe { xcb::cast_event::<xcb::SelectionRequestEvent>(&event) };
+        assert_eq!(event.target(), manager.atoms.save_targets);
+
+        let reply = xcb::get_property(
+            &manager.connection, false, event.requestor(),
+            event.property(), xcb::ATOM_ANY, 0, 1024
+        ).get_reply().unwrap();
+        assert_eq!(reply.type_(), xcb::ATOM_ATOM);
+        assert_eq!(reply.format(), 32);
+
+        xcb::send_event(
+            &manager.connection, false, event.requestor(), 0,
+            &xcb::SelectionNotifyEvent::new(
+                event.time(), event.requestor(), event.selection(), event.target(), event.property()
+            )
+        );
+        manager.connection.flush();
+        break reply.value::<Atom>().to_vec();
+    });
+
+    clipboard.persist(dur).unwrap();
+    assert_eq!(handle.join().unwrap(), targets);
+}
+
+#[test]
+fn it_work_multiple() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_MULTIPLE").unwrap());
+    let atom_utf8string = clipboard.getter.atoms.utf8_string;
+    let atom_png = clipboard.getter.get_atom("image/png").unwrap();
+    let atom_pairs = clipboard.getter.get_atom("X11_CLIPBOARD_TEST_MULTIPLE_PAIRS").unwrap();
+    let atom_first = clipboard.getter.get_atom("X11_CLIPBOARD_TEST_MULTIPLE_FIRST").unwrap();
+    let atom_second = clipboard.getter.get_atom("X11_CLIPBOARD_TEST_MULTIPLE_SECOND").unwrap();
+    let atom_multiple = clipboard.getter.atoms.multiple;
+
+    clipboard.store(selection, Target::Utf8String, "text").unwrap();
+
+    // Ask for a supported and an unsupported target at once.
+    xcb::change_property(
+        &clipboard.getter.connection, xcb::PROP_MODE_REPLACE as u8,
+        clipboard.getter.window, atom_pairs, clipboard.getter.atoms.atom_pair, 32,
+        &[atom_utf8string, atom_first, atom_png, atom_second]
+    );
+    clipboard.getter.connection.flush();
+
+    let output = clipboard.load(selection, Target::Custom(atom_multiple), atom_pairs, Duration::from_secs(3)).unwrap();
+    let pairs = output.chunks_exact(4)
+        .map(|chunk| Atom::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect::<Vec<_>>();
+    assert_eq!(pairs, vec![atom_utf8string, atom_first, atom_png, xcb::ATOM_NONE]);
+
+    let reply = xcb::get_property(
+        &clipboard.getter.connection, true, clipboard.getter.window,
+        atom_first, xcb::ATOM_ANY, 0, 1024
+    ).get_reply().unwrap();
+    assert_eq!(reply.type_(), atom_utf8string);
+    assert_eq!(reply.value::<u8>(), b"text");
+
+    let reply = xcb::get_property(
+        &clipboard.getter.connection, false, clipboard.getter.window,
+        atom_second, xcb::ATOM_ANY, 0, 1024
+    ).get_reply().unwrap();
+    assert_eq!(reply.type_(), xcb::ATOM_NONE);
+
+    // Pairs can only be read from a property of format 32, anything else is refused.
+    xcb::change_property(
+        &clipboard.getter.connection, xcb::PROP_MODE_REPLACE as u8,
+        clipboard.getter.window, atom_pairs, clipboard.getter.atoms.atom_pair, 8,
+        b"12345678"
+    );
+    clipboard.getter.connection.flush();
+
+    let output = clipboard.load(selection, Target::Custom(atom_multiple), atom_pairs, Duration::from_secs(3)).unwrap();
+    assert!(output.is_empty());
+}
+
+struct CountProvider {
+    target: Atom,
+    refused: Atom,
+    count: Arc<AtomicUsize>
+}
+
+impl Provider for CountProvider {
+    fn targets(&self) -> Vec<Atom> {
+        vec![self.target, self.refused]
+    }
+
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        if target == self.target {
+            let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+            Some(count.to_string().into_bytes())
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn it_work_provider() {
+    let clipboard = Clipboard::new().unwrap();
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_PROVIDER").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+
+    clipboard.store_provider(selection, CountProvider {
+        target: clipboard.setter.atoms.utf8_string,
+        refused: clipboard.setter.atoms.string,
+        count: Arc::clone(&count)
+    }).unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, Duration::from_secs(3)).unwrap();
+    assert_eq!(output, b"1");
+
+    let output = clipboard.load(selection, Target::String, atom_property, Duration::from_secs(3)).unwrap();
+    assert!(output.is_empty());
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn it_work_watcher() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_WATCHER").unwrap());
+
+    let watcher = SelectionWatcher::new(vec![selection]).unwrap();
+    clipboard.store(selection, Target::Utf8String, "text").unwrap();
+
+    let event = watcher.wait().unwrap();
+    assert_eq!(event.selection, selection);
+    assert_eq!(event.owner, clipboard.setter.window);
+    assert_eq!(event.kind, ChangeKind::SetOwner);
+}
+
+#[test]
+fn it_work_watcher_multiple_selections() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let first = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_WATCHER_FIRST").unwrap());
+    let second = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_WATCHER_SECOND").unwrap());
+
+    let watcher = SelectionWatcher::new(vec![first, second]).unwrap();
+
+    clipboard.store(first, Target::Utf8String, "first").unwrap();
+    assert_eq!(watcher.wait().unwrap().selection, first);
+
+    clipboard.store(second, Target::Utf8String, "second").unwrap();
+    assert_eq!(watcher.wait().unwrap().selection, second);
+}
+
+#[test]
+fn it_work_ownership_lost() {
+    let clipboard = Clipboard::new().unwrap();
+    let other = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_OWNERSHIP_LOST").unwrap());
+
+    let lost = clipboard.ownership_lost().unwrap();
+    clipboard.store(selection, Target::Utf8String, "first").unwrap();
+    other.store(selection, Target::Utf8String, "second").unwrap();
+
+    let event = lost.recv_timeout(Duration::from_secs(3)).unwrap();
+    assert_eq!(event.selection, selection);
+    assert_eq!(Some(event.time), other.ownership_timestamp(selection).unwrap());
+    assert_eq!(clipboard.ownership_timestamp(selection).unwrap(), None);
+}
+
+#[test]
+fn it_work_load_at() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_LOAD_AT").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+
+    clipboard.store(selection, Target::Utf8String, "text").unwrap();
+
+    let time = clipboard.ownership_timestamp(selection).unwrap();
+    let output = clipboard.load_at(selection, Target::Utf8String, atom_property, time, Duration::from_secs(3)).unwrap();
+    assert_eq!(output, b"text");
+}
+
+#[test]
+fn it_work_concurrent_incr() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_CONCURRENT_INCR").unwrap());
+
+    let data = Arc::new(incr_data(&clipboard));
+    clipboard.store(selection, Target::Utf8String, data.as_slice()).unwrap();
+
+    let handles = (0..2)
+        .map(|_| {
+            let data = Arc::clone(&data);
+            thread::spawn(move || {
+                let clipboard = Clipboard::new().unwrap();
+                let atom_property = clipboard.getter.atoms.property;
+                let output = clipboard.load(selection, Target::Utf8String, atom_property, Duration::from_secs(60)).unwrap();
+                assert_eq!(output.len(), data.len());
+                assert!(output == *data);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn it_work_load_into() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_LOAD_INTO").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+
+    let data = incr_data(&clipboard);
+    let size = data.len();
+    clipboard.store(selection, Target::Utf8String, data.as_slice()).unwrap();
+
+    let mut output = Vec::new();
+    let mut chunks = 0;
+    let mut last = 0;
+    let len = clipboard.load_into(
+        selection, Target::Utf8String, atom_property,
+        &mut output,
+        |received, total| {
+            assert!(received > last);
+            assert_eq!(total, Some(size));
+            chunks += 1;
+            last = received;
+        },
+        Duration::from_secs(60)
+    ).unwrap();
+
+    assert_eq!(len, size);
+    assert_eq!(last, size);
+    assert!(chunks > 1);
+    assert!(output == data);
+}
+
+#[test]
+fn it_work_store_reader() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_STORE_READER").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+
+    let data = incr_data(&clipboard);
+    clipboard.store_reader(selection, Target::Utf8String, Cursor::new(data.clone())).unwrap();
+
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, Duration::from_secs(60)).unwrap();
+    assert!(output == data);
+}
+
+#[test]
+fn it_work_max_size() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_MAX_SIZE").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+    let dur = Duration::from_secs(60);
+
+    clipboard.store(selection, Target::Utf8String, "0123456789").unwrap();
+
+    clipboard.set_max_size(Some(10));
+    assert_eq!(clipboard.load(selection, Target::Utf8String, atom_property, dur).unwrap(), b"0123456789");
+
+    clipboard.set_max_size(Some(9));
+    match clipboard.load(selection, Target::Utf8String, atom_property, dur) {
+        Err(Error::TooLarge(9)) => (),
+        other => panic!("unexpected result: {:?}", other)
+    }
+
+    let data = incr_data(&clipboard);
+    clipboard.store(selection, Target::Utf8String, data.as_slice()).unwrap();
+
+    clipboard.set_max_size(Some(data.len() - 1));
+    match clipboard.load(selection, Target::Utf8String, atom_property, dur) {
+        Err(Error::TooLarge(_)) => (),
+        other => panic!("unexpected result: {:?}", other.map(|output| output.len()))
+    }
+}
+
+#[test]
+fn it_work_text() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_TEXT").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+    let dur = Duration::from_secs(3);
+
+    clipboard.store_text(selection, "caf\u{e9} \u{2603}").unwrap();
+
+    let output = clipboard.load(selection, Target::String, atom_property, dur).unwrap();
+    assert_eq!(output, b"caf\xe9 ?");
+
+    let output = clipboard.load_text(selection, dur).unwrap();
+    assert_eq!(output, "caf\u{e9} \u{2603}");
+
+    // TEXT is answered as STRING when Latin-1 suffices, and as COMPOUND_TEXT otherwise.
+    let getter = &clipboard.getter;
+    let load_as_text = || {
+        xcb::convert_selection(
+            &getter.connection, getter.window,
+            selection.atom(&getter.atoms), getter.atoms.text, atom_property,
+            xcb::CURRENT_TIME
+        );
+        getter.connection.flush();
+        loop {
+            let event = getter.connection.wait_for_event().unwrap();
+            if event.response_type() & !0x80 == xcb::SELECTION_NOTIFY { break }
+        }
+        let reply = xcb::get_property(
+            &getter.connection, true, getter.window,
+            atom_property, xcb::ATOM_ANY, 0, 1024
+        ).get_reply().unwrap();
+        (reply.type_(), reply.value::<u8>().to_vec())
+    };
+
+    let (type_, output) = load_as_text();
+    assert_eq!(type_, getter.atoms.compound_text);
+    assert_eq!(text::decode_compound_text(&output), "caf\u{e9} \u{2603}");
+
+    clipboard.store_text(selection, "caf\u{e9}").unwrap();
+    let (type_, output) = load_as_text();
+    assert_eq!(type_, getter.atoms.string);
+    assert_eq!(output, b"caf\xe9");
+}
+
+#[test]
+fn it_work_compound_text() {
+    let data = "caf\u{e9} \u{2603}\u{1f600} \u{3b1}\u{3b2}";
+    let output = text::encode_compound_text(data);
+    assert_eq!(&output[..5], b"caf\xe9 ");
+    assert_eq!(text::decode_compound_text(&output), data);
+
+    // Extended segments are decoded when they name UTF-8, under either name.
+    let extended = |name: &[u8], text: &str| {
+        let len = name.len() + 1 + text.len();
+        let mut value = vec![0x1b, b'%', b'/', b'1', 0x80 | (len / 128) as u8, 0x80 | (len % 128) as u8];
+        value.extend_from_slice(name);
+        value.push(0x02);
+        value.extend_from_slice(text.as_bytes());
+        value
+    };
+    for &name in &[&b"utf-8"[..], b"UTF-8", b"iso10646-1", b"ISO10646-1"] {
+        let mut value = b"a".to_vec();
+        value.extend(extended(name, "\u{2603}\u{3b1}"));
+        value.push(b'b');
+        assert_eq!(text::decode_compound_text(&value), "a\u{2603}\u{3b1}b");
+    }
+
+    let mut value = extended(b"koi8-r", "\u{2603}");
+    value.push(b'b');
+    assert_eq!(text::decode_compound_text(&value), "\u{fffd}b");
+}
+
+#[test]
+fn it_work_selection_owner() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_SELECTION_OWNER").unwrap());
+    assert_eq!(clipboard.getter.selection_owner(selection).unwrap(), None);
+
+    clipboard.store_text(selection, "owner").unwrap();
+
+    let owner = clipboard.getter.selection_owner(selection).unwrap().unwrap();
+    assert_eq!(owner.window, clipboard.setter.window);
+    assert_eq!(owner.toplevel, clipboard.setter.window);
+
+    // The owner is this process, connected to a server on the same machine.
+    if cfg!(feature = "xres") {
+        assert_eq!(owner.client_pid, Some(std::process::id()));
+    }
+}
+
+#[test]
+fn it_work_text_decoding() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_TEXT_DECODING").unwrap());
+    let dur = Duration::from_secs(3);
+
+    clipboard.store(selection, Target::Utf8String, &b"text\0\0"[..]).unwrap();
+    assert_eq!(clipboard.load_text(selection, dur).unwrap(), "text");
+
+    clipboard.store(selection, Target::Utf8String, &b"\xff\xfe"[..]).unwrap();
+    match clipboard.load_text(selection, dur) {
+        Err(Error::Utf8(_)) => (),
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn it_work_set_text() {
+    let _lock = lock_clipboard();
+    let clipboard = Clipboard::new().unwrap();
+
+    let text = format!("caf\u{e9} {:?}", Instant::now());
+    clipboard.set_text(text.as_str()).unwrap();
+    assert_eq!(clipboard.get_text(Duration::from_secs(3)).unwrap(), text);
+}
+
+#[test]
+fn it_work_files() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_FILES").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+    let dur = Duration::from_secs(3);
+
+    let data = FileList {
+        operation: FileOperation::Cut,
+        paths: vec![PathBuf::from("/tmp/a b"), PathBuf::from("/tmp/caf\u{e9}")]
+    };
+    clipboard.store_files(selection, data.clone()).unwrap();
+
+    assert_eq!(clipboard.load_files(selection, dur).unwrap(), data);
+
+    let output = clipboard.load(selection, files::uri_list(), atom_property, dur).unwrap();
+    assert_eq!(output, b"file:///tmp/a%20b\r\nfile:///tmp/caf%C3%A9\r\n");
+
+    let relative = FileList {
+        operation: FileOperation::Copy,
+        paths: vec![PathBuf::from("/tmp/a"), PathBuf::from("b")]
+    };
+    match clipboard.store_files(selection, relative) {
+        Err(Error::RelativePath(path)) => assert_eq!(path, PathBuf::from("b")),
+        other => panic!("expected a relative path error, got {:?}", other)
+    }
+    assert_eq!(clipboard.load_files(selection, dur).unwrap(), data);
+}
+
+#[test]
+fn it_work_html() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_HTML").unwrap());
+    let dur = Duration::from_secs(3);
+
+    let data = "<p>fish &amp; <b>chips</b></p><p>peas</p>";
+    clipboard.store_html(selection, data).unwrap();
+
+    assert_eq!(clipboard.load_html(selection, dur).unwrap(), Content::Html(data.to_owned()));
+    assert_eq!(clipboard.load_text(selection, dur).unwrap(), "fish & chips\npeas");
+
+    clipboard.store_text(selection, "plain").unwrap();
+    assert_eq!(clipboard.load_html(selection, dur).unwrap(), Content::Text("plain".to_owned()));
+}
+
+#[test]
+fn it_work_load_best() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_LOAD_BEST").unwrap());
+    let dur = Duration::from_secs(3);
+
+    clipboard.store_targets(selection, vec![
+        (Target::String, "text"),
+        (Target::text_html(), "<p>text</p>")
+    ]).unwrap();
+
+    let preferred = [Target::image_png(), Target::text_html(), Target::String];
+    let (target, output) = clipboard.load_best(selection, &preferred, dur).unwrap();
+    assert_eq!(target, Target::text_html());
+    assert_eq!(output, b"<p>text</p>");
+
+    match clipboard.load_best(selection, &[Target::image_png()], dur) {
+        Err(Error::NoTarget) => (),
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn it_work_refuse_unsupported_target() {
+    let clipboard = Clipboard::new().unwrap();
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_REFUSE").unwrap());
+    let atom_property = clipboard.setter.atoms.property;
+    let dur = Duration::from_secs(3);
+
+    clipboard.store(selection, Target::Utf8String, "text").unwrap();
+
+    let output = clipboard.load(selection, Target::image_png(), atom_property, dur).unwrap();
+    assert!(output.is_empty());
+
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, dur).unwrap();
+    assert_eq!(output, b"text");
+}
+
+#[test]
+fn it_work_store_during_load_wait() {
+    let clipboard = Arc::new(Clipboard::new().unwrap());
+
+    let selection = Selection::Custom(clipboard.setter.get_atom("X11_CLIPBOARD_TEST_STORE_DURING_LOAD_WAIT").unwrap());
+    let atom_property = clipboard.getter.atoms.property;
+
+    let handle = {
+        let clipboard = Arc::clone(&clipboard);
+        thread::spawn(move || clipboard.load_wait(selection, Target::Utf8String, atom_property).unwrap())
+    };
+
+    // Give the loader time to start waiting on the getter connection.
+    thread::sleep(Duration::from_millis(200));
+    clipboard.store(selection, Target::Utf8String, "text").unwrap();
+
+    assert_eq!(handle.join().unwrap(), b"text");
+}
+
+#[test]
+fn it_work_incr_timeout() {
+    let clipboard = Clipboard::new().unwrap();
+    clipboard.set_incr_timeout(Duration::from_millis(200));
+
+    let atom_selection = clipboard.setter.get_atom("X11_CLIPBOARD_TEST_INCR_TIMEOUT").unwrap();
+    let atom_property = clipboard.getter.get_atom("X11_CLIPBOARD_TEST_INCR_TIMEOUT_PROPERTY").unwrap();
+    let atom_marker = clipboard.getter.get_atom("X11_CLIPBOARD_TEST_INCR_TIMEOUT_MARKER").unwrap();
+    let atom_utf8string = clipboard.getter.atoms.utf8_string;
+    let selection = Selection::Custom(atom_selection);
+    let getter = &clipboard.getter;
+
+    let data = incr_data(&clipboard);
+    clipboard.store(selection, Target::Utf8String, data.as_slice()).unwrap();
+
+    // Start an incremental transfer by hand, then never delete the property.
+    xcb::convert_selection(
+        &getter.connection, getter.window,
+        atom_selection, atom_utf8string, atom_property,
+        xcb::CURRENT_TIME
+    );
+    getter.connection.flush();
+    loop {
+        let event = getter.connection.wait_for_event().unwrap();
+        if event.response_type() & !0x80 == xcb::SELECTION_NOTIFY { break }
+    }
+    let reply = xcb::get_property(
+        &getter.connection, false, getter.window,
+        atom_property, xcb::ATOM_ANY, 0, 1
+    ).get_reply().unwrap();
+    assert_eq!(reply.type_(), getter.atoms.incr);
+
+    // The deadline is at most the timeout away, and the owner checks it
+    // before handling the deletion, however late it gets to run.
+    thread::sleep(Duration::from_millis(300));
+
+    // The owner dropped the transfer, so deleting the property yields no chunk.
+    // Requesting TIMESTAMP afterwards tells when the owner handled the deletion,
+    // as it handles its events in order.
+    xcb::delete_property(&getter.connection, getter.window, atom_property);
+    xcb::convert_selection(
+        &getter.connection, getter.window,
+        atom_selection, getter.atoms.timestamp, atom_marker,
+        xcb::CURRENT_TIME
+    );
+    getter.connection.flush();
+    loop {
+        let event = getter.connection.wait_for_event().unwrap();
+        if event.response_type() & !0x80 == xcb::SELECTION_NOTIFY { break }
+    }
+    let reply = xcb::get_property(
+        &getter.connection, false, getter.window,
+        atom_property, xcb::ATOM_ANY, 0, 1
+    ).get_reply().unwrap();
+    assert_eq!(reply.type_(), xcb::ATOM_NONE);
+
+    // A new transfer on the same window and property starts from the beginning,
+    // with a timeout that a slow machine can't run into.
+    clipboard.set_incr_timeout(Duration::from_secs(5));
+    let output = clipboard.load(selection, Target::Utf8String, atom_property, Duration::from_secs(60)).unwrap();
+    assert!(output == data);
+}